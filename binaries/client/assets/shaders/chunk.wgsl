@@ -3,15 +3,29 @@
 struct Vertex {
     @builtin(instance_index) instance_index: u32,
     @location(0) data: u32,
+    @location(1) face: u32,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) uvw: vec3<f32>,
     @location(1) normal: vec3<f32>,
+    @location(2) @interpolate(flat) face: u32,
+    @location(3) occlusion: f32,
+    @location(4) light_uvw: vec3<f32>,
 };
 
-const CHUNK_SIZE: f32 = 31.0;
+const CHUNK_SIZE: f32 = f32(#{CHUNK_SIZE}u);
+const PADDED_SIZE: f32 = CHUNK_SIZE + 2.0;
+
+var<private> normals: array<vec3<f32>,6> = array<vec3<f32>,6>(
+    vec3<f32>(-1.0, 0.0, 0.0), // Left
+    vec3<f32>(1.0, 0.0, 0.0),  // Right
+    vec3<f32>(0.0, -1.0, 0.0), // Down
+    vec3<f32>(0.0, 1.0, 0.0),  // Up
+    vec3<f32>(0.0, 0.0, -1.0), // Back
+    vec3<f32>(0.0, 0.0, 1.0),  // Front
+);
 
 var<private> colors: array<vec3<f32>,17> = array<vec3<f32>,17>(
     vec3<f32>(0.00, 0.0, 0.00), // Air, should not be rendered
@@ -22,17 +36,37 @@ var<private> colors: array<vec3<f32>,17> = array<vec3<f32>,17>(
     vec3<f32>(0.35, 0.20, 0.0),  // Woods
     vec3<f32>(0.07, 0.3, 0.07), // Leaves
     vec3<f32>(0.15, 0.6, 0.2), // Light Leaves
-    vec3<f32>(0.3, 0.3, 0.3),   // Dark Grey
-    vec3<f32>(0.0, 0.75, 1.0),  // Sky blue
-    vec3<f32>(1.0, 0.5, 0.0),   // Orange
-    vec3<f32>(1.0, 0.2, 0.2),   // Coral Red
-    vec3<f32>(0.5, 0.0, 0.5),   // Purple
-    vec3<f32>(1.0, 1.0, 0.0),   // Yellow
+    vec3<f32>(1.0, 0.85, 0.4),  // Lamp
+    vec3<f32>(0.8, 0.9, 0.95),  // Glass
+    vec3<f32>(0.1, 0.35, 0.8),  // Water
+    vec3<f32>(0.86, 0.8, 0.55), // Sand
+    vec3<f32>(0.5, 0.48, 0.45), // Gravel
+    vec3<f32>(0.4, 0.36, 0.3),  // Debris
     vec3<f32>(0.9, 0.1, 0.8),   // Magenta
     vec3<f32>(0.0, 0.0, 0.75),  // Deep Blue
     vec3<f32>(1.0, 0.6, 0.8),   // Pastel Pink
 );
 
+// Opacity of the transparent blocks, the others are rendered without blending
+var<private> alphas: array<f32,16> = array<f32,16>(
+    0.0, // Air
+    1.0, // Grass
+    1.0, // Dirt
+    1.0, // Stone
+    1.0, // Light Grass
+    1.0, // Woods
+    0.9, // Leaves
+    0.9, // Light Leaves
+    1.0, // Lamp
+    0.3, // Glass
+    0.6, // Water
+    1.0, // Sand
+    1.0, // Gravel
+    1.0, // Debris
+    1.0,
+    1.0,
+);
+
 fn x_positive_bits(bits: u32) -> u32 {
     return (1u << bits) - 1u;
 }
@@ -45,19 +79,17 @@ fn interpolate(x: f32) -> f32 {
 fn vertex(vertex: Vertex) -> VertexOutput {
     var out: VertexOutput;
 
-    let x = f32(vertex.data & x_positive_bits(5u));
-    let y = f32(vertex.data >> 5u & x_positive_bits(5u));
-    let z = f32(vertex.data >> 10u & x_positive_bits(5u));
+    let x = f32(vertex.data & x_positive_bits(6u));
+    let y = f32(vertex.data >> 6u & x_positive_bits(6u));
+    let z = f32(vertex.data >> 12u & x_positive_bits(6u));
 
-    let u_o = interpolate(f32(vertex.data >> 15u & x_positive_bits(1u)));
-    let v_o = interpolate(f32(vertex.data >> 16u & x_positive_bits(1u)));
-    let w_o = interpolate(f32(vertex.data >> 17u & x_positive_bits(1u)));
+    let u_o = interpolate(f32(vertex.data >> 18u & x_positive_bits(1u)));
+    let v_o = interpolate(f32(vertex.data >> 19u & x_positive_bits(1u)));
+    let w_o = interpolate(f32(vertex.data >> 20u & x_positive_bits(1u)));
 
-    let n_x = f32(vertex.data >> 18u & x_positive_bits(2u)) - 1.0;
-    let n_y = f32(vertex.data >> 20u & x_positive_bits(2u)) - 1.0;
-    let n_z = f32(vertex.data >> 22u & x_positive_bits(2u)) - 1.0;
+    let direction = vertex.data >> 21u & x_positive_bits(3u);
 
-    let health = f32(vertex.data >> 24u & x_positive_bits(4u)) / 15.0;
+    let occlusion = f32(vertex.data >> 28u & x_positive_bits(2u)) / 3.0;
 
     out.clip_position = mesh_position_local_to_clip(
         get_world_from_local(vertex.instance_index),
@@ -68,30 +100,51 @@ fn vertex(vertex: Vertex) -> VertexOutput {
     out.uvw.y = y + v_o * 0.25;
     out.uvw.z = z + w_o * 0.25;
 
+    out.normal = normals[direction];
+
+    // The light is read in the voxel in front of the face, the light texture has a one voxel apron
+    out.light_uvw = (out.uvw + out.normal * 0.5 + 1.0) / PADDED_SIZE;
+
     out.uvw = out.uvw / CHUNK_SIZE;
-    out.normal = vec3<f32>(n_x, n_y, n_z);
+    out.face = vertex.face;
+    out.occlusion = occlusion;
 
     return out;
 }
 
 @group(2) @binding(0) var chunk: texture_3d<f32>;
 @group(2) @binding(1) var chunk_sampler: sampler;
+@group(2) @binding(2) var light: texture_3d<f32>;
+@group(2) @binding(3) var light_sampler: sampler;
 
 struct FragmentInput {
     @location(0) uvw: vec3<f32>,
     @location(1) normal: vec3<f32>,
+    @location(2) @interpolate(flat) face: u32,
+    @location(3) occlusion: f32,
+    @location(4) light_uvw: vec3<f32>,
 };
 
 @fragment
 fn fragment(input: FragmentInput) -> @location(0) vec4<f32> {
-    let block = u32(textureSample(chunk, chunk_sampler, input.uvw).x * 255.0);
+    // Faces that carry their own block and health take precedence over the chunk texture
+    let sampled = u32(textureSample(chunk, chunk_sampler, input.uvw).x * 255.0);
+    let block = select(sampled, input.face, (input.face & x_positive_bits(4u)) != 0u);
 
     let id = block & x_positive_bits(4u);
-    let health = f32(block >> 4u) / 15.0;
+    // Water stores its level instead of its health
+    let health = select(f32(block >> 4u) / 15.0, 1.0, id == 10u);
 
     let color = colors[id % 16u];
 
     let modifier = dot(abs(input.normal), vec3<f32>(0.15, 0.18, 0.12));
 
-    return vec4<f32>((color - modifier) * health, 1.0);
+    let ambient = 1.0 - input.occlusion * 0.5;
+
+    let levels = u32(textureSample(light, light_sampler, input.light_uvw).x * 255.0);
+    let sky = f32(levels >> 4u);
+    let emitted = f32(levels & x_positive_bits(4u));
+    let brightness = pow(0.8, 15.0 - max(sky, emitted));
+
+    return vec4<f32>((color - modifier) * health * ambient * brightness, alphas[id % 16u]);
 }
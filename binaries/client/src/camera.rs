@@ -0,0 +1,82 @@
+use bevy::prelude::*;
+use logic::look::PlayerLook;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+pub const CAMERA_SETTINGS_PATH: &str = "config/camera.ron";
+
+pub struct FirstPersonCamera;
+
+impl Plugin for FirstPersonCamera {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(config::load_or_default::<CameraSettings>(
+            CAMERA_SETTINGS_PATH,
+        ));
+        app.add_systems(Update, (update_projection, follow_player_look));
+    }
+}
+
+#[derive(Debug, Clone, Resource, Serialize, Deserialize)]
+pub struct CameraSettings {
+    // Vertical field of view in degrees
+    pub fov: f32,
+    // Time in seconds for the camera to catch up with the look, 0 follows it directly
+    pub smoothing: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            fov: 70.0,
+            smoothing: 0.0,
+        }
+    }
+}
+
+// Camera attached to the head of a player, its body only turns with the yaw
+#[derive(Debug, Default, Component)]
+pub struct PlayerCamera {
+    // Smoothed rotation in world space
+    pub rotation: Quat,
+}
+
+fn update_projection(
+    settings: Res<CameraSettings>,
+    mut cameras: Query<(Ref<PlayerCamera>, &mut Projection)>,
+) {
+    for (camera, mut projection) in &mut cameras {
+        if !settings.is_changed() && !camera.is_added() {
+            continue;
+        }
+
+        if let Projection::Perspective(perspective) = &mut *projection {
+            perspective.fov = settings.fov.to_radians();
+        }
+    }
+}
+
+fn follow_player_look(
+    time: Res<Time>,
+    settings: Res<CameraSettings>,
+    players: Query<&PlayerLook>,
+    mut cameras: Query<(&Parent, &mut PlayerCamera, &mut Transform)>,
+) {
+    for (parent, mut camera, mut transform) in &mut cameras {
+        let Ok(look) = players.get(parent.get()) else {
+            continue;
+        };
+
+        let target = look.rotation();
+
+        camera.rotation = match settings.smoothing > 0.0 {
+            true => camera.rotation.slerp(
+                target,
+                1.0 - (-time.delta_seconds() / settings.smoothing).exp(),
+            ),
+            false => target,
+        };
+
+        transform.rotation = look.body_rotation().inverse() * camera.rotation;
+    }
+}
@@ -0,0 +1,41 @@
+use std::{fs::File, io::Read, io::Write, path::Path};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+pub fn load<T: DeserializeOwned>(path: impl AsRef<Path>) -> eyre::Result<T> {
+    let mut content = String::new();
+    File::open(path)?.read_to_string(&mut content)?;
+
+    Ok(ron::from_str(&content)?)
+}
+
+pub fn save<T: Serialize>(value: &T, path: impl AsRef<Path>) -> eyre::Result<()> {
+    if let Some(parent) = path.as_ref().parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let content = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())?;
+
+    File::create(path)?.write_all(content.as_bytes())?;
+
+    Ok(())
+}
+
+// A missing config is written with the default values so that it can be edited
+pub fn load_or_default<T: Serialize + DeserializeOwned + Default>(path: impl AsRef<Path>) -> T {
+    if !path.as_ref().exists() {
+        let value = T::default();
+
+        if let Err(error) = save(&value, path) {
+            eprintln!("{}", error);
+        }
+
+        return value;
+    }
+
+    load(path).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+
+        T::default()
+    })
+}
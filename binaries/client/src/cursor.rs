@@ -3,6 +3,8 @@ use bevy::{
     window::{CursorGrabMode, PrimaryWindow},
 };
 
+use crate::input::{Action, ActionInput};
+
 pub struct CursorGrabber;
 
 impl Plugin for CursorGrabber {
@@ -11,19 +13,15 @@ impl Plugin for CursorGrabber {
     }
 }
 
-fn cursor_grab(
-    mouse_input: Res<ButtonInput<MouseButton>>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut windows: Query<&mut Window, With<PrimaryWindow>>,
-) {
-    if mouse_input.just_pressed(MouseButton::Left) {
+fn cursor_grab(actions: ActionInput, mut windows: Query<&mut Window, With<PrimaryWindow>>) {
+    if actions.just_pressed(Action::Focus) {
         for mut window in &mut windows {
             window.cursor.grab_mode = CursorGrabMode::Locked;
             window.cursor.visible = false;
         }
     }
 
-    if keyboard_input.just_pressed(KeyCode::Escape) {
+    if actions.just_pressed(Action::Unfocus) {
         for mut window in &mut windows {
             window.cursor.grab_mode = CursorGrabMode::None;
             window.cursor.visible = true;
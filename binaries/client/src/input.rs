@@ -0,0 +1,307 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    ecs::system::SystemParam,
+    input::{
+        gamepad::{GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType},
+        mouse::MouseMotion,
+    },
+    prelude::*,
+};
+use logic::{
+    input::PendingInput,
+    player::{MovementMode, Player, PlayerFocus},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+pub const ACTION_MAP_PATH: &str = "config/input.ron";
+
+// Sticks are ignored below this deflection
+const GAMEPAD_DEADZONE: f32 = 0.15;
+
+pub struct InputMapping;
+
+impl Plugin for InputMapping {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(config::load_or_default::<ActionMap>(ACTION_MAP_PATH));
+        app.add_systems(Update, collect_player_input);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Crouch,
+    Fire,
+    Build,
+    Reload,
+    NextWeapon,
+    ToggleNoclip,
+    Spectate,
+    Focus,
+    Unfocus,
+    GenerateChunk,
+    ExportRegion,
+    CycleMesher,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+}
+
+// Bindings of every action, loaded from the user config so they can be changed without recompiling
+#[derive(Debug, Clone, Resource, Serialize, Deserialize)]
+pub struct ActionMap {
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+
+    // Radians per pixel of mouse motion, and per second at full deflection of the right stick
+    pub mouse_sensitivity: f32,
+    pub gamepad_sensitivity: f32,
+    pub invert_y: bool,
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        use Binding::*;
+
+        let bindings = [
+            (Action::MoveForward, vec![Key(KeyCode::KeyW)]),
+            (Action::MoveBackward, vec![Key(KeyCode::KeyS)]),
+            (Action::MoveLeft, vec![Key(KeyCode::KeyA)]),
+            (Action::MoveRight, vec![Key(KeyCode::KeyD)]),
+            (
+                Action::Jump,
+                vec![Key(KeyCode::Space), Gamepad(GamepadButtonType::South)],
+            ),
+            (
+                Action::Crouch,
+                vec![Key(KeyCode::ShiftLeft), Gamepad(GamepadButtonType::East)],
+            ),
+            (
+                Action::Fire,
+                vec![
+                    Mouse(MouseButton::Left),
+                    Gamepad(GamepadButtonType::RightTrigger2),
+                ],
+            ),
+            (
+                Action::Build,
+                vec![
+                    Mouse(MouseButton::Right),
+                    Gamepad(GamepadButtonType::LeftTrigger2),
+                ],
+            ),
+            (
+                Action::Reload,
+                vec![Key(KeyCode::KeyR), Gamepad(GamepadButtonType::West)],
+            ),
+            (
+                Action::NextWeapon,
+                vec![Key(KeyCode::KeyQ), Gamepad(GamepadButtonType::North)],
+            ),
+            (Action::ToggleNoclip, vec![Key(KeyCode::KeyV)]),
+            (Action::Spectate, vec![Key(KeyCode::KeyB)]),
+            (
+                Action::Focus,
+                vec![Mouse(MouseButton::Left), Gamepad(GamepadButtonType::Start)],
+            ),
+            (
+                Action::Unfocus,
+                vec![Key(KeyCode::Escape), Gamepad(GamepadButtonType::Select)],
+            ),
+            (Action::GenerateChunk, vec![Key(KeyCode::Enter)]),
+            (Action::ExportRegion, vec![Key(KeyCode::F9)]),
+            (Action::CycleMesher, vec![Key(KeyCode::F3)]),
+        ];
+
+        Self {
+            bindings: bindings.into_iter().collect(),
+            mouse_sensitivity: 0.005,
+            gamepad_sensitivity: 3.0,
+            invert_y: false,
+        }
+    }
+}
+
+impl ActionMap {
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings
+            .get(&action)
+            .map(|bindings| bindings.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+// State of the actions from every device
+#[derive(SystemParam)]
+pub struct ActionInput<'w> {
+    pub map: Res<'w, ActionMap>,
+    pub keyboard: Res<'w, ButtonInput<KeyCode>>,
+    pub mouse: Res<'w, ButtonInput<MouseButton>>,
+    pub gamepad_buttons: Res<'w, ButtonInput<GamepadButton>>,
+    pub gamepad_axes: Res<'w, Axis<GamepadAxis>>,
+    pub gamepads: Res<'w, Gamepads>,
+}
+
+impl ActionInput<'_> {
+    fn any(&self, action: Action, check: impl Fn(Binding) -> bool) -> bool {
+        self.map
+            .bindings(action)
+            .iter()
+            .any(|binding| check(*binding))
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.any(action, |binding| match binding {
+            Binding::Key(key) => self.keyboard.pressed(key),
+            Binding::Mouse(button) => self.mouse.pressed(button),
+            Binding::Gamepad(button) => self.gamepads.iter().any(|gamepad| {
+                self.gamepad_buttons
+                    .pressed(GamepadButton::new(gamepad, button))
+            }),
+        })
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.any(action, |binding| match binding {
+            Binding::Key(key) => self.keyboard.just_pressed(key),
+            Binding::Mouse(button) => self.mouse.just_pressed(button),
+            Binding::Gamepad(button) => self.gamepads.iter().any(|gamepad| {
+                self.gamepad_buttons
+                    .just_pressed(GamepadButton::new(gamepad, button))
+            }),
+        })
+    }
+
+    fn axis(&self, positive: Action, negative: Action) -> f32 {
+        self.pressed(positive) as i32 as f32 - self.pressed(negative) as i32 as f32
+    }
+
+    fn stick(&self, x: GamepadAxisType, y: GamepadAxisType) -> Vec2 {
+        self.gamepads
+            .iter()
+            .map(|gamepad| {
+                let axis = |axis_type| {
+                    self.gamepad_axes
+                        .get(GamepadAxis::new(gamepad, axis_type))
+                        .unwrap_or_default()
+                };
+
+                Vec2::new(axis(x), axis(y))
+            })
+            .find(|stick| stick.length() > GAMEPAD_DEADZONE)
+            .unwrap_or_default()
+    }
+
+    // Right and forward
+    pub fn movement(&self) -> Vec2 {
+        let keys = Vec2::new(
+            self.axis(Action::MoveRight, Action::MoveLeft),
+            self.axis(Action::MoveForward, Action::MoveBackward),
+        );
+
+        (keys + self.stick(GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY))
+            .clamp_length_max(1.0)
+    }
+
+    // Yaw and pitch in radians
+    pub fn look(&self, mouse_motion: Vec2, delta: f32) -> Vec2 {
+        let stick = self.stick(GamepadAxisType::RightStickX, GamepadAxisType::RightStickY);
+
+        let mut look = -mouse_motion * self.map.mouse_sensitivity
+            + Vec2::new(-stick.x, stick.y) * self.map.gamepad_sensitivity * delta;
+
+        if self.map.invert_y {
+            look.y = -look.y;
+        }
+
+        look
+    }
+}
+
+fn collect_player_input(
+    time: Res<Time>,
+    actions: ActionInput,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut players: Query<(
+        Entity,
+        &mut PendingInput,
+        &MovementMode,
+        Option<Ref<PlayerFocus>>,
+    )>,
+    others: Query<Entity, With<Player>>,
+) {
+    let motion = mouse_motion
+        .read()
+        .fold(Vec2::ZERO, |motion, event| motion + event.delta);
+
+    for (entity, mut pending, mode, focus) in &mut players {
+        // Players without the focus stop moving
+        let Some(focus) = focus else {
+            *pending = PendingInput::default();
+
+            continue;
+        };
+
+        // Focus and Fire share the left click, the click giving the focus is consumed and doesn't shoot.
+        // Fire is held only after a press made with the focus.
+        let fire_pressed = actions.just_pressed(Action::Fire) && !focus.is_added();
+        let firing = actions.pressed(Action::Fire) && (pending.1 || fire_pressed);
+        pending.1 = firing;
+
+        let input = &mut pending.0;
+
+        input.movement = actions.movement();
+        input.vertical = actions.axis(Action::Jump, Action::Crouch);
+        input.look += actions.look(motion, time.delta_seconds());
+        input.jump = actions.pressed(Action::Jump);
+        input.crouch = actions.pressed(Action::Crouch);
+        input.fire |= firing || fire_pressed;
+        input.build = actions.pressed(Action::Build);
+        input.reload = actions.pressed(Action::Reload);
+        input.next_weapon |= actions.just_pressed(Action::NextWeapon);
+
+        if actions.just_pressed(Action::ToggleNoclip) {
+            input.mode = Some(match mode {
+                MovementMode::Noclip => MovementMode::Walking,
+                _ => MovementMode::Noclip,
+            });
+        }
+
+        if actions.just_pressed(Action::Spectate) {
+            input.mode = Some(next_spectator_mode(entity, *mode, &others));
+        }
+    }
+}
+
+// Cycle through the other players, then back to walking
+fn next_spectator_mode(
+    entity: Entity,
+    mode: MovementMode,
+    others: &Query<Entity, With<Player>>,
+) -> MovementMode {
+    let mut targets = others
+        .iter()
+        .filter(|other| *other != entity)
+        .collect::<Vec<_>>();
+    targets.sort();
+
+    let next = match mode {
+        MovementMode::Spectator { target } => targets.into_iter().find(|other| *other > target),
+        _ => targets.into_iter().next(),
+    };
+
+    match next {
+        Some(target) => MovementMode::Spectator { target },
+        None => MovementMode::Walking,
+    }
+}
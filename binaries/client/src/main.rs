@@ -3,14 +3,42 @@ use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_screen_diagnostics::{
     ScreenDiagnosticsPlugin, ScreenEntityDiagnosticsPlugin, ScreenFrameDiagnosticsPlugin,
 };
+use camera::{FirstPersonCamera, PlayerCamera};
 use cursor::CursorGrabber;
-use logic::player::{Player, PlayerFocus, PlayerManagement};
-use render::world::VoxelWorldRenderer;
-use voxel::world::{chunk::CHUNK_SIZE, VoxelWorld, VoxelWorldPlugin};
+use input::{Action, ActionInput, InputMapping};
+use logic::{
+    arsenal::Loadout,
+    controller::CharacterController,
+    health::{Armor, Dead, Health, HealthManagement},
+    input::{PendingInput, PlayerInput},
+    look::PlayerLook,
+    player::{MovementMode, Player, PlayerFocus, PlayerManagement, PlayerPermissions},
+    projectile::Projectiles,
+    simulation::Simulation,
+    spawn::SpawnArea,
+    weapon::{Hitbox, Weapons},
+};
+use render::{
+    projectile::ProjectileRenderer,
+    world::{chunk::ChunkMesherKind, VoxelWorldRenderer},
+};
+use voxel::world::{
+    coords::{BlockPos, ChunkPos},
+    vox::ExportVox,
+    VoxelWorld, VoxelWorldPlugin,
+};
 
+pub mod camera;
+pub mod config;
 pub mod cursor;
+pub mod input;
 pub mod render;
 
+pub const LOADOUT_PATH: &str = "config/loadout.ron";
+
+// Horizontal position where the player used to start, inside the generated chunks and away from their borders
+pub const SPAWN_CENTER: Vec2 = Vec2::new(-17.526, 57.248);
+
 #[derive(Debug, Component)]
 pub struct MainPlayer;
 
@@ -27,31 +55,45 @@ fn main() {
                 })
                 .set(ImagePlugin::default_nearest()),
         )
-        .add_plugins((CursorGrabber, PlayerManagement))
-        .add_plugins((VoxelWorldPlugin, VoxelWorldRenderer))
+        .add_plugins((InputMapping, CursorGrabber, FirstPersonCamera))
+        .add_plugins((
+            Simulation,
+            PlayerManagement,
+            Weapons,
+            Projectiles,
+            HealthManagement,
+        ))
+        .add_plugins((VoxelWorldPlugin, VoxelWorldRenderer, ProjectileRenderer))
         .add_plugins(WorldInspectorPlugin::default())
         .add_plugins(ScreenDiagnosticsPlugin::default())
         .add_plugins(ScreenEntityDiagnosticsPlugin)
         .add_plugins(ScreenFrameDiagnosticsPlugin)
         .insert_resource(ClearColor(Color::srgb(0.72, 1.0, 0.98)))
-        .add_systems(Update, (focus_player, add_chunk_to_world))
+        .add_systems(
+            Update,
+            (
+                focus_player,
+                add_chunk_to_world,
+                export_region,
+                cycle_mesher,
+            ),
+        )
         .add_systems(Startup, (setup, construct_world))
         .run();
 }
 
 fn focus_player(
     mut commands: Commands,
-    mouse_input: Res<ButtonInput<MouseButton>>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    actions: ActionInput,
     mut players: Query<Entity, With<MainPlayer>>,
 ) {
-    if mouse_input.just_pressed(MouseButton::Left) {
+    if actions.just_pressed(Action::Focus) {
         for player in &mut players {
             commands.entity(player).insert(PlayerFocus);
         }
     }
 
-    if keyboard_input.just_pressed(KeyCode::Escape) {
+    if actions.just_pressed(Action::Unfocus) {
         for player in &mut players {
             commands.entity(player).remove::<PlayerFocus>();
         }
@@ -61,34 +103,12 @@ fn focus_player(
 fn add_chunk_to_world(
     mut world: Query<&mut VoxelWorld>,
     player_transform: Query<&Transform, With<PlayerFocus>>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    actions: ActionInput,
 ) {
-    if keyboard_input.just_pressed(KeyCode::Enter) {
+    if actions.just_pressed(Action::GenerateChunk) {
         for pos in &player_transform {
             for mut world in &mut world {
-                let pos = {
-                    let x = pos.translation.x as i32;
-                    let y = pos.translation.y as i32;
-                    let z = pos.translation.z as i32;
-
-                    let x = if x < 0 {
-                        x / CHUNK_SIZE as i32 - 1
-                    } else {
-                        x / CHUNK_SIZE as i32
-                    };
-                    let y = if y < 0 {
-                        y / CHUNK_SIZE as i32 - 1
-                    } else {
-                        y / CHUNK_SIZE as i32
-                    };
-                    let z = if z < 0 {
-                        z / CHUNK_SIZE as i32 - 1
-                    } else {
-                        z / CHUNK_SIZE as i32
-                    };
-
-                    IVec3::new(x, y, z)
-                };
+                let pos = ChunkPos::from(pos.translation);
 
                 world.generate(vec![pos]);
             }
@@ -96,12 +116,43 @@ fn add_chunk_to_world(
     }
 }
 
+fn export_region(
+    mut events: EventWriter<ExportVox>,
+    player_transform: Query<&Transform, With<PlayerFocus>>,
+    actions: ActionInput,
+) {
+    if actions.just_pressed(Action::ExportRegion) {
+        for pos in &player_transform {
+            let pos = BlockPos::from(pos.translation);
+
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default();
+
+            events.send(ExportVox {
+                min: pos.offset(IVec3::splat(-64)),
+                max: pos.offset(IVec3::splat(64)),
+                path: format!("exports/region_{}.vox", timestamp),
+            });
+        }
+    }
+}
+
+fn cycle_mesher(mut mesher: ResMut<ChunkMesherKind>, actions: ActionInput) {
+    if actions.just_pressed(Action::CycleMesher) {
+        *mesher = mesher.next();
+
+        info!("Meshing chunks with the {:?} mesher", *mesher);
+    }
+}
+
 fn construct_world(mut commands: Commands) {
     let mut chunks = Vec::new();
     for x in -9..=9 {
         for y in 0..=2 {
             for z in -9..=9 {
-                chunks.push(IVec3::new(x, y, z));
+                chunks.push(ChunkPos::new(x, y, z));
             }
         }
     }
@@ -120,23 +171,51 @@ fn construct_world(mut commands: Commands) {
 }
 
 fn setup(mut commands: Commands) {
-    let mut player = commands.spawn((Player, MainPlayer));
-
-    let mut transform = Transform::from_xyz(-17.526, 78.574, 57.248);
-    transform.rotate_x(-0.5);
-    transform.rotate_y(-0.5);
-
-    player.insert(Camera3dBundle {
-        projection: PerspectiveProjection {
-            near: 0.1,
-            far: 100.0,
-            fov: 70.0,
-            ..Default::default()
-        }
-        .into(),
-        transform,
-        ..Default::default()
+    commands.insert_resource(SpawnArea {
+        center: SPAWN_CENTER,
+        ..default()
     });
 
+    let mut player = commands.spawn((
+        Player,
+        MainPlayer,
+        CharacterController::default(),
+        PlayerInput::default(),
+        PendingInput::default(),
+        PlayerLook::new(-0.5, -0.5),
+        MovementMode::Walking,
+        config::load_or_default::<Loadout>(LOADOUT_PATH),
+        Hitbox::character(),
+        Health::new(100.0),
+        Armor::new(50.0, 0.5),
+        // The player is placed on the terrain once it is generated
+        Dead::joined(),
+    ));
+
+    // Development builds can fly and spectate
+    if cfg!(debug_assertions) {
+        player.insert(PlayerPermissions::all());
+    } else {
+        player.insert(PlayerPermissions::default());
+    }
+
+    player.insert(SpatialBundle::default());
+
     player.insert(Name::new("Player"));
+
+    // The field of view comes from the camera settings
+    player.with_children(|parent| {
+        parent
+            .spawn(Camera3dBundle {
+                projection: PerspectiveProjection {
+                    near: 0.1,
+                    far: 100.0,
+                    ..Default::default()
+                }
+                .into(),
+                ..Default::default()
+            })
+            .insert(PlayerCamera::default())
+            .insert(Name::new("Camera"));
+    });
 }
@@ -0,0 +1,34 @@
+use bevy::prelude::*;
+use logic::projectile::Projectile;
+
+pub struct ProjectileRenderer;
+
+impl Plugin for ProjectileRenderer {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, add_projectile_mesh);
+    }
+}
+
+pub fn add_projectile_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    projectiles: Query<(Entity, &Projectile), Without<Handle<Mesh>>>,
+) {
+    for (entity, projectile) in &projectiles {
+        // Explosives are bigger and glow so they can be dodged
+        let (radius, emissive) = match projectile.definition.explosion_radius > 0.0 {
+            true => (0.2, LinearRgba::rgb(4.0, 1.5, 0.2)),
+            false => (0.05, LinearRgba::BLACK),
+        };
+
+        commands.entity(entity).insert((
+            meshes.add(Sphere::new(radius)),
+            materials.add(StandardMaterial {
+                base_color: Color::srgb(0.2, 0.2, 0.2),
+                emissive,
+                ..default()
+            }),
+        ));
+    }
+}
@@ -1,8 +1,10 @@
 use ::voxel::world::{
-    chunk::{Chunk, ChunkUpdated},
+    chunk::{Chunk, ChunkBorderUpdated, ChunkLightUpdated, ChunkUpdated, TerrainGenerated},
+    gravity::FallingBlock,
     VoxelWorld,
 };
 use bevy::prelude::*;
+use chunk::{ChunkMesherKind, ChunkTransparentMesh, UnmeshedModels, UpdatedChunks};
 use voxel::ChunkMaterial;
 
 pub mod chunk;
@@ -13,47 +15,130 @@ pub struct VoxelWorldRenderer;
 impl Plugin for VoxelWorldRenderer {
     fn build(&self, app: &mut App) {
         app.add_plugins(MaterialPlugin::<ChunkMaterial>::default());
-        app.add_systems(Update, (generate_chunk_mesh, update_chunk_mesh));
+        app.init_resource::<ChunkMesherKind>();
+        app.add_systems(
+            Update,
+            (
+                remesh_on_mesher_change,
+                generate_chunk_mesh,
+                update_chunk_mesh,
+                update_chunk_light,
+                add_falling_block_mesh,
+                generate_model_mesh,
+            ),
+        );
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn generate_chunk_mesh(
     commands: Commands,
     meshes: ResMut<Assets<Mesh>>,
     materials: ResMut<Assets<ChunkMaterial>>,
     images: ResMut<Assets<Image>>,
+    mesher: Res<ChunkMesherKind>,
     chunks: Query<(&Parent, Entity, &Chunk), Without<Handle<Mesh>>>,
-    all_chunks: Query<&Chunk>,
+    states: Query<(Has<TerrainGenerated>, Has<ChunkBorderUpdated>), With<Chunk>>,
     world: Query<&VoxelWorld>,
 ) {
     if let Err(error) = chunk::generate_chunk_mesh(
-        commands, meshes, materials, images, chunks, all_chunks, world,
+        commands, meshes, materials, images, mesher, chunks, states, world,
     ) {
         eprintln!("{}", error)
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn update_chunk_mesh(
     commands: Commands,
     meshes: ResMut<Assets<Mesh>>,
     materials: ResMut<Assets<ChunkMaterial>>,
     images: ResMut<Assets<Image>>,
+    mesher: Res<ChunkMesherKind>,
+    chunks: UpdatedChunks,
+    transparent_meshes: Query<(&Handle<Mesh>, &Handle<ChunkMaterial>), Without<Chunk>>,
+) {
+    if let Err(error) = chunk::update_chunk_mesh(
+        commands,
+        meshes,
+        materials,
+        images,
+        mesher,
+        chunks,
+        transparent_meshes,
+    ) {
+        eprintln!("{}", error)
+    }
+}
+
+pub fn generate_model_mesh(
+    commands: Commands,
+    meshes: ResMut<Assets<Mesh>>,
+    materials: ResMut<Assets<ChunkMaterial>>,
+    images: ResMut<Assets<Image>>,
+    mesher: Res<ChunkMesherKind>,
+    models: UnmeshedModels,
+) {
+    if let Err(error) =
+        chunk::generate_model_mesh(commands, meshes, materials, images, mesher, models)
+    {
+        eprintln!("{}", error)
+    }
+}
+
+pub fn remesh_on_mesher_change(
+    mut commands: Commands,
+    mesher: Res<ChunkMesherKind>,
+    chunks: Query<Entity, (With<Chunk>, With<Handle<Mesh>>)>,
+) {
+    if mesher.is_changed() && !mesher.is_added() {
+        for chunk in &chunks {
+            commands.entity(chunk).insert(ChunkUpdated);
+        }
+    }
+}
+
+pub fn update_chunk_light(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    mut images: ResMut<Assets<Image>>,
     chunks: Query<
         (
-            &Parent,
             Entity,
-            &Handle<Mesh>,
             &Handle<ChunkMaterial>,
+            &ChunkTransparentMesh,
             &Chunk,
         ),
-        With<ChunkUpdated>,
+        With<ChunkLightUpdated>,
     >,
-    all_chunks: Query<&Chunk>,
-    world: Query<&VoxelWorld>,
+    transparent_materials: Query<&Handle<ChunkMaterial>, Without<Chunk>>,
 ) {
-    if let Err(error) = chunk::update_chunk_mesh(
-        commands, meshes, materials, images, chunks, all_chunks, world,
-    ) {
-        eprintln!("{}", error)
+    for (chunk_id, material, transparent, chunk) in &chunks {
+        let transparent = transparent_materials.get(transparent.0).ok();
+
+        for material in std::iter::once(material).chain(transparent) {
+            if let Some(material) = materials.get_mut(material.id()) {
+                material.update_light(chunk, &mut images);
+            }
+        }
+
+        commands.entity(chunk_id).remove::<ChunkLightUpdated>();
+    }
+}
+
+pub fn add_falling_block_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    blocks: Query<(Entity, &FallingBlock), Without<Handle<Mesh>>>,
+) {
+    for (entity, falling) in &blocks {
+        commands.entity(entity).insert((
+            meshes.add(Cuboid::from_length(1.0)),
+            materials.add(StandardMaterial {
+                base_color: Color::from(falling.block.color()),
+                ..default()
+            }),
+        ));
     }
 }
@@ -3,137 +3,312 @@ use bevy::{
     render::{mesh::PrimitiveTopology, render_asset::RenderAssetUsages},
 };
 
-use greedy_mesher::GreedyMesh;
+use attribute_mesher::AttributeMesher;
+use culler::CulledMesher;
+use greedy_mesher::GreedyMesher;
 use voxel::world::{
-    chunk::{Chunk, ChunkNeighbors, ChunkUpdated, CHUNK_SIZE},
+    chunk::{Chunk, ChunkBorderUpdated, ChunkUpdated, TerrainGenerated, PADDED_SIZE},
+    coords::ChunkPos,
+    model::VoxelModel,
     VoxelWorld,
 };
 
-use super::voxel::{ChunkMaterial, ATTRIBUTE_VOXEL};
+use super::voxel::{ChunkMaterial, Direction, Quad, ATTRIBUTE_VOXEL, ATTRIBUTE_VOXEL_FACE};
 
+pub mod attribute_mesher;
 pub mod culler;
 pub mod greedy_mesher;
 
-fn get_chunk<'a>(all_chunks: &'a Query<&Chunk>, entity: Option<Entity>) -> Option<&'a Chunk> {
-    match entity {
-        Some(entity) => all_chunks.get(entity).ok(),
-        None => None,
+#[derive(Debug, Default)]
+pub struct ChunkMesh {
+    pub vertices: Vec<u32>,
+    pub faces: Vec<u32>,
+    pub indices: Vec<u32>,
+}
+
+pub trait ChunkMesher {
+    fn mesh(chunk: &Chunk, layer: ChunkLayer) -> eyre::Result<ChunkMesh>;
+}
+
+// Opaque and transparent faces are meshed apart so the transparent ones can be blended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkLayer {
+    Opaque,
+    Transparent,
+}
+
+// Child entity of a chunk holding the mesh of its transparent faces
+#[derive(Debug, Component)]
+pub struct ChunkTransparentMesh(pub Entity);
+
+// Meshed chunks whose blocks changed since
+pub type UpdatedChunks<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Handle<Mesh>,
+        &'static Handle<ChunkMaterial>,
+        &'static ChunkTransparentMesh,
+        &'static Chunk,
+    ),
+    With<ChunkUpdated>,
+>;
+
+pub type UnmeshedModels<'w, 's> =
+    Query<'w, 's, (Entity, &'static Chunk), (With<VoxelModel>, Without<Handle<Mesh>>)>;
+
+// Mesher used for every chunk, changing it remeshes the whole world
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Resource)]
+pub enum ChunkMesherKind {
+    Culled,
+    #[default]
+    Greedy,
+    Attribute,
+}
+
+impl ChunkMesh {
+    pub fn push_quad(&mut self, quad: Quad, face: u32) {
+        self.faces
+            .resize(self.faces.len() + quad.vertices.len(), face);
+        self.vertices.extend(quad.vertices);
+        self.indices.extend(quad.indices);
+    }
+
+    pub fn build(self) -> Mesh {
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        );
+
+        self.apply(&mut mesh);
+
+        mesh
+    }
+
+    pub fn apply(self, mesh: &mut Mesh) {
+        mesh.insert_attribute(ATTRIBUTE_VOXEL, self.vertices);
+        mesh.insert_attribute(ATTRIBUTE_VOXEL_FACE, self.faces);
+        mesh.insert_indices(bevy::render::mesh::Indices::U32(self.indices));
+    }
+}
+
+impl ChunkLayer {
+    // Masks of the voxels meshed in this layer and of the voxels hiding their faces, for the column (i, j) of each axis
+    pub fn axes(&self, chunk: &Chunk, i: usize, j: usize) -> [(u64, u64); 3] {
+        let index = (i + 1) + (j + 1) * PADDED_SIZE;
+        let (opaque, transparent) = (&chunk.opaque, &chunk.transparent);
+
+        match self {
+            ChunkLayer::Opaque => [
+                (opaque.x_axis[index], opaque.x_axis[index]),
+                (opaque.y_axis[index], opaque.y_axis[index]),
+                (opaque.z_axis[index], opaque.z_axis[index]),
+            ],
+            ChunkLayer::Transparent => [
+                (
+                    transparent.x_axis[index],
+                    transparent.x_axis[index] | opaque.x_axis[index],
+                ),
+                (
+                    transparent.y_axis[index],
+                    transparent.y_axis[index] | opaque.y_axis[index],
+                ),
+                (
+                    transparent.z_axis[index],
+                    transparent.z_axis[index] | opaque.z_axis[index],
+                ),
+            ],
+        }
+    }
+
+    pub fn is_visible(&self, chunk: &Chunk, pos: IVec3, direction: Direction) -> bool {
+        let front = pos + direction.normal();
+
+        match self {
+            ChunkLayer::Opaque => chunk.is_opaque(pos) && !chunk.is_opaque(front),
+            ChunkLayer::Transparent => {
+                chunk.is_transparent(pos) && !chunk.is_opaque(front) && !chunk.is_transparent(front)
+            }
+        }
     }
 }
 
+impl ChunkMesherKind {
+    pub fn mesh(&self, chunk: &Chunk, layer: ChunkLayer) -> eyre::Result<ChunkMesh> {
+        match self {
+            ChunkMesherKind::Culled => CulledMesher::mesh(chunk, layer),
+            ChunkMesherKind::Greedy => GreedyMesher::mesh(chunk, layer),
+            ChunkMesherKind::Attribute => AttributeMesher::mesh(chunk, layer),
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            ChunkMesherKind::Culled => ChunkMesherKind::Greedy,
+            ChunkMesherKind::Greedy => ChunkMesherKind::Attribute,
+            ChunkMesherKind::Attribute => ChunkMesherKind::Culled,
+        }
+    }
+}
+
+// A chunk is meshed once all its loaded neighbours are generated and their borders copied in its apron,
+// so that it is not meshed again each time one of them streams in
+fn is_ready(
+    world: &VoxelWorld,
+    pos: ChunkPos,
+    states: &Query<(Has<TerrainGenerated>, Has<ChunkBorderUpdated>), With<Chunk>>,
+) -> bool {
+    ChunkPos::aabb(pos.offset(IVec3::NEG_ONE), pos.offset(IVec3::ONE)).all(|pos| {
+        match world
+            .chunks
+            .get(&pos)
+            .and_then(|entity| states.get(*entity).ok())
+        {
+            Some((generated, border_updated)) => generated && !border_updated,
+            None => true,
+        }
+    })
+}
+
+// Mesh both layers of the chunk, the transparent one is spawned apart to be added as a child of the chunk.
+// The child sits at the chunk origin so the transparent layers are only ordered roughly between chunks.
+fn spawn_chunk_meshes(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<ChunkMaterial>>,
+    images: &mut ResMut<Assets<Image>>,
+    mesher: &ChunkMesherKind,
+    chunk: &Chunk,
+) -> eyre::Result<(Handle<Mesh>, Handle<ChunkMaterial>, Entity)> {
+    let opaque = mesher.mesh(chunk, ChunkLayer::Opaque)?.build();
+    let transparent = mesher.mesh(chunk, ChunkLayer::Transparent)?.build();
+
+    let material = ChunkMaterial::new(chunk, images);
+    let transparent_material = material.transparent();
+
+    let transparent_id = commands
+        .spawn(MaterialMeshBundle {
+            mesh: meshes.add(transparent),
+            material: materials.add(transparent_material),
+            ..default()
+        })
+        .insert(Name::new("Transparent faces"))
+        .id();
+
+    Ok((meshes.add(opaque), materials.add(material), transparent_id))
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn generate_chunk_mesh(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ChunkMaterial>>,
     mut images: ResMut<Assets<Image>>,
+    mesher: Res<ChunkMesherKind>,
     chunks: Query<(&Parent, Entity, &Chunk), Without<Handle<Mesh>>>,
-    all_chunks: Query<&Chunk>,
+    states: Query<(Has<TerrainGenerated>, Has<ChunkBorderUpdated>), With<Chunk>>,
     world: Query<&VoxelWorld>,
 ) -> eyre::Result<()> {
     for (parent, chunk_id, chunk) in &chunks {
         if let Ok(world) = world.get(parent.get()) {
-            let ChunkNeighbors {
-                left,
-                right,
-                top,
-                bottom,
-                front,
-                back,
-            } = world.neighbours(chunk.pos);
-
-            let (left, right, top, bottom, front, back) = (
-                get_chunk(&all_chunks, left),
-                get_chunk(&all_chunks, right),
-                get_chunk(&all_chunks, top),
-                get_chunk(&all_chunks, bottom),
-                get_chunk(&all_chunks, front),
-                get_chunk(&all_chunks, back),
-            );
-
-            let GreedyMesh { vertices, indices } =
-                GreedyMesh::new(chunk, left, right, bottom, top, back, front)?;
-
-            let mut mesh = Mesh::new(
-                PrimitiveTopology::TriangleList,
-                RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-            );
-
-            mesh.insert_attribute(ATTRIBUTE_VOXEL, vertices);
-            mesh.insert_indices(bevy::render::mesh::Indices::U32(indices));
-
-            let material = materials.add(ChunkMaterial::new(chunk, &mut images));
-
-            commands.entity(chunk_id).insert(MaterialMeshBundle {
-                mesh: meshes.add(mesh),
-                material,
-                transform: Transform::from_xyz(
-                    CHUNK_SIZE as f32 * chunk.pos.x as f32,
-                    CHUNK_SIZE as f32 * chunk.pos.y as f32,
-                    CHUNK_SIZE as f32 * chunk.pos.z as f32,
-                ),
-                ..default()
-            });
+            if !is_ready(world, chunk.pos, &states) {
+                continue;
+            }
+
+            let (mesh, material, transparent_id) = spawn_chunk_meshes(
+                &mut commands,
+                &mut meshes,
+                &mut materials,
+                &mut images,
+                &mesher,
+                chunk,
+            )?;
+
+            commands
+                .entity(chunk_id)
+                .insert(MaterialMeshBundle {
+                    mesh,
+                    material,
+                    transform: Transform::from_translation(chunk.pos.origin().as_vec3()),
+                    ..default()
+                })
+                .insert(ChunkTransparentMesh(transparent_id))
+                .add_child(transparent_id);
+
+            commands.entity(chunk_id).remove::<ChunkUpdated>();
         }
     }
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn update_chunk_mesh(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ChunkMaterial>>,
     mut images: ResMut<Assets<Image>>,
-    chunks: Query<
-        (
-            &Parent,
-            Entity,
-            &Handle<Mesh>,
-            &Handle<ChunkMaterial>,
-            &Chunk,
-        ),
-        With<ChunkUpdated>,
-    >,
-    all_chunks: Query<&Chunk>,
-    world: Query<&VoxelWorld>,
+    mesher: Res<ChunkMesherKind>,
+    chunks: UpdatedChunks,
+    transparent_meshes: Query<(&Handle<Mesh>, &Handle<ChunkMaterial>), Without<Chunk>>,
 ) -> eyre::Result<()> {
-    for (parent, chunk_id, mesh, material, chunk) in &chunks {
-        if let Ok(world) = world.get(parent.get()) {
-            let ChunkNeighbors {
-                left,
-                right,
-                top,
-                bottom,
-                front,
-                back,
-            } = world.neighbours(chunk.pos);
-
-            let (left, right, top, bottom, front, back) = (
-                get_chunk(&all_chunks, left),
-                get_chunk(&all_chunks, right),
-                get_chunk(&all_chunks, top),
-                get_chunk(&all_chunks, bottom),
-                get_chunk(&all_chunks, front),
-                get_chunk(&all_chunks, back),
-            );
-
-            let GreedyMesh { vertices, indices } =
-                GreedyMesh::new(chunk, left, right, bottom, top, back, front)?;
+    // World chunks and models are updated the same way once they have a mesh
+    for (chunk_id, mesh, material, transparent, chunk) in &chunks {
+        let mut layers = vec![(mesh, material, ChunkLayer::Opaque)];
+
+        if let Ok((mesh, material)) = transparent_meshes.get(transparent.0) {
+            layers.push((mesh, material, ChunkLayer::Transparent));
+        }
+
+        for (mesh, material, layer) in layers {
+            let data = mesher.mesh(chunk, layer)?;
 
             if let Some(mesh) = meshes.get_mut(mesh.id()) {
                 mesh.remove_attribute(ATTRIBUTE_VOXEL);
+                mesh.remove_attribute(ATTRIBUTE_VOXEL_FACE);
                 mesh.remove_indices();
 
-                mesh.insert_attribute(ATTRIBUTE_VOXEL, vertices);
-                mesh.insert_indices(bevy::render::mesh::Indices::U32(indices));
+                data.apply(mesh);
             }
 
+            // Both materials share the same textures, but each one has to be marked as changed
             if let Some(material) = materials.get_mut(material.id()) {
                 material.update(chunk, &mut images);
             }
-
-            commands.entity(chunk_id).remove::<ChunkUpdated>();
         }
+
+        commands.entity(chunk_id).remove::<ChunkUpdated>();
+    }
+
+    Ok(())
+}
+
+// Models are meshed in their own space, the transform of their entity places them
+pub fn generate_model_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    mesher: Res<ChunkMesherKind>,
+    models: UnmeshedModels,
+) -> eyre::Result<()> {
+    for (model_id, chunk) in &models {
+        let (mesh, material, transparent_id) = spawn_chunk_meshes(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &mut images,
+            &mesher,
+            chunk,
+        )?;
+
+        commands
+            .entity(model_id)
+            .insert((mesh, material, ChunkTransparentMesh(transparent_id)))
+            .add_child(transparent_id);
+
+        commands.entity(model_id).remove::<ChunkUpdated>();
     }
 
     Ok(())
@@ -0,0 +1,128 @@
+use bevy::math::{IVec3, UVec3};
+use voxel::world::{
+    chunk::{Chunk, CHUNK_SIZE},
+    coords::LocalPos,
+};
+
+use crate::render::world::{
+    chunk::{ChunkLayer, ChunkMesh, ChunkMesher},
+    voxel::{ambient_occlusion, Direction, Quad},
+};
+
+// Greedy mesher that only merges faces of the same block type, health and occlusion, so every quad carries its own
+// attributes
+pub struct AttributeMesher;
+
+// Position of the voxel at (i, j) in the k-th plane facing the direction, j is the axis along which faces are merged first
+fn plane_pos(direction: Direction, k: usize, i: usize, j: usize) -> IVec3 {
+    let (k, i, j) = (k as i32, i as i32, j as i32);
+
+    match direction {
+        Direction::Left | Direction::Right => IVec3::new(k, i, j),
+        Direction::Down | Direction::Up => IVec3::new(i, k, j),
+        Direction::Back | Direction::Front => IVec3::new(i, j, k),
+    }
+}
+
+fn quad_size(direction: Direction, w: usize, h: usize) -> UVec3 {
+    let (w, h) = (w as u32, h as u32);
+
+    match direction {
+        Direction::Left | Direction::Right => UVec3::new(1, w, h),
+        Direction::Down | Direction::Up => UVec3::new(w, 1, h),
+        Direction::Back | Direction::Front => UVec3::new(w, h, 1),
+    }
+}
+
+// Block and health of the face with the occlusion of its corners
+type Face = (u8, u8);
+
+// Every visible face of the plane, the block is 0 where there is no face
+fn plane_faces(
+    chunk: &Chunk,
+    layer: ChunkLayer,
+    direction: Direction,
+    k: usize,
+) -> [[Face; CHUNK_SIZE]; CHUNK_SIZE] {
+    let mut faces = [[(0, 0); CHUNK_SIZE]; CHUNK_SIZE];
+
+    for (i, row) in faces.iter_mut().enumerate() {
+        for (j, face) in row.iter_mut().enumerate() {
+            let pos = plane_pos(direction, k, i, j);
+
+            if layer.is_visible(chunk, pos, direction) {
+                *face = (
+                    chunk.blocks[LocalPos::from(pos.as_uvec3()).index()],
+                    ambient_occlusion(chunk, pos, direction),
+                );
+            }
+        }
+    }
+
+    faces
+}
+
+fn push_plane(
+    mesh: &mut ChunkMesh,
+    mut faces: [[Face; CHUNK_SIZE]; CHUNK_SIZE],
+    direction: Direction,
+    k: usize,
+) {
+    for i in 0..CHUNK_SIZE {
+        let mut j = 0;
+
+        while j < CHUNK_SIZE {
+            let face = faces[i][j];
+            let (block, ao) = face;
+
+            if block == 0 {
+                j += 1;
+                continue;
+            }
+
+            let mut h = 1;
+            while j + h < CHUNK_SIZE && faces[i][j + h] == face {
+                h += 1;
+            }
+
+            let mut w = 1;
+            while i + w < CHUNK_SIZE && faces[i + w][j..j + h].iter().all(|next| *next == face) {
+                faces[i + w][j..j + h].fill((0, 0));
+
+                w += 1;
+            }
+
+            mesh.push_quad(
+                Quad::from_direction(
+                    direction,
+                    mesh.vertices.len(),
+                    plane_pos(direction, k, i, j),
+                    quad_size(direction, w, h),
+                )
+                .with_occlusion(ao),
+                block as u32,
+            );
+
+            j += h;
+        }
+    }
+}
+
+impl ChunkMesher for AttributeMesher {
+    fn mesh(chunk: &Chunk, layer: ChunkLayer) -> eyre::Result<ChunkMesh> {
+        let mut mesh = ChunkMesh::default();
+
+        for direction in Direction::ALL {
+            for k in 0..CHUNK_SIZE {
+                push_plane(
+                    &mut mesh,
+                    plane_faces(chunk, layer, direction, k),
+                    direction,
+                    k,
+                );
+            }
+        }
+
+        Ok(mesh)
+    }
+}
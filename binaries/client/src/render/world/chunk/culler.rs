@@ -1,173 +1,91 @@
 use bevy::math::{IVec3, UVec3};
-use voxel::world::chunk::{Chunk, CHUNK_SIZE};
+use voxel::world::chunk::{Chunk, CHUNK_SIZE, INNER_MASK};
 
-use crate::render::world::voxel::{Direction, Quad};
+use crate::render::world::{
+    chunk::{ChunkLayer, ChunkMesh, ChunkMesher},
+    voxel::{Direction, Quad},
+};
 
-pub struct CulledMesh {
-    pub vertices: Vec<u32>,
-    pub indices: Vec<u32>,
-}
-
-fn push_face(vertices: &mut Vec<u32>, indices: &mut Vec<u32>, pos: IVec3, direction: Direction) {
-    let mut quad = Quad::from_direction(direction, vertices.len(), pos, UVec3::ONE);
+pub struct CulledMesher;
 
-    vertices.append(&mut quad.vertices);
-    indices.append(&mut quad.indices);
+fn push_face(mesh: &mut ChunkMesh, pos: IVec3, direction: Direction) {
+    mesh.push_quad(
+        Quad::from_direction(direction, mesh.vertices.len(), pos, UVec3::ONE),
+        0,
+    );
 }
 
-fn line_axis(axis: u32, before: bool, after: bool) -> (u32, u32) {
-    let visible_asc = match after {
-        true => !(axis >> 1 | 0b01000000000000000000000000000000) & axis,
-        false => !(axis >> 1) & axis,
-    };
+fn line_axis(axis: u64, occluders: u64) -> (u64, u64) {
+    let visible_asc = !(occluders >> 1) & axis & INNER_MASK;
+    let visible_desc = !(occluders << 1) & axis & INNER_MASK;
 
-    let visible_desc = match before {
-        true => !(axis << 1 | 1) & axis,
-        false => !(axis << 1) & axis,
-    };
-
-    (visible_asc, visible_desc)
+    (visible_asc >> 1, visible_desc >> 1)
 }
 
 fn push_face_axis(
-    vertices: &mut Vec<u32>,
-    indices: &mut Vec<u32>,
+    mesh: &mut ChunkMesh,
     count: u32,
     i: usize,
     j: usize,
     k: usize,
-    visible: u32,
+    visible: u64,
     direction: Direction,
-) -> eyre::Result<()> {
+) {
     if visible & (1 << count) != 0 {
         let pos = IVec3::new(i as i32, j as i32, k as i32);
 
-        push_face(vertices, indices, pos, direction);
+        push_face(mesh, pos, direction);
     }
-
-    Ok(())
 }
 
-impl CulledMesh {
-    pub fn new(
-        chunk: &Chunk,
-        left: Option<&Chunk>,
-        right: Option<&Chunk>,
-        bottom: Option<&Chunk>,
-        top: Option<&Chunk>,
-        back: Option<&Chunk>,
-        front: Option<&Chunk>,
-    ) -> eyre::Result<Self> {
-        let mut vertices = Vec::<u32>::new();
-        let mut indices = Vec::<u32>::new();
+impl ChunkMesher for CulledMesher {
+    fn mesh(chunk: &Chunk, layer: ChunkLayer) -> eyre::Result<ChunkMesh> {
+        let mut mesh = ChunkMesh::default();
 
         for i in 0..CHUNK_SIZE {
             for j in 0..CHUNK_SIZE {
-                let x_axis = chunk.x_axis[i + j * CHUNK_SIZE];
-                let y_axis = chunk.y_axis[i + j * CHUNK_SIZE];
-                let z_axis = chunk.z_axis[i + j * CHUNK_SIZE];
-
-                let left = left
-                    .map(|chunk| chunk.x_axis[i + j * CHUNK_SIZE] & (1 << (CHUNK_SIZE - 1)) != 0)
-                    .unwrap_or(false);
-
-                let right = right
-                    .map(|chunk| chunk.x_axis[i + j * CHUNK_SIZE] & 1 != 0)
-                    .unwrap_or(false);
-
-                let bottom = bottom
-                    .map(|chunk| chunk.y_axis[i + j * CHUNK_SIZE] & (1 << (CHUNK_SIZE - 1)) != 0)
-                    .unwrap_or(false);
-
-                let top = top
-                    .map(|chunk| chunk.y_axis[i + j * CHUNK_SIZE] & 1 != 0)
-                    .unwrap_or(false);
-
-                let back = back
-                    .map(|chunk| chunk.z_axis[i + j * CHUNK_SIZE] & (1 << (CHUNK_SIZE - 1)) != 0)
-                    .unwrap_or(false);
-
-                let front = front
-                    .map(|chunk| chunk.z_axis[i + j * CHUNK_SIZE] & 1 != 0)
-                    .unwrap_or(false);
-
-                let (visible_right, visible_left) = line_axis(x_axis, left, right);
-                let (visible_top, visible_bottom) = line_axis(y_axis, bottom, top);
-                let (visible_front, visible_back) = line_axis(z_axis, back, front);
-
-                let success: eyre::Result<()> = {
-                    for k in 0..CHUNK_SIZE {
-                        push_face_axis(
-                            &mut vertices,
-                            &mut indices,
-                            k as u32,
-                            k,
-                            i,
-                            j,
-                            visible_left,
-                            Direction::Left,
-                        )?;
-                        push_face_axis(
-                            &mut vertices,
-                            &mut indices,
-                            k as u32,
-                            k,
-                            i,
-                            j,
-                            visible_right,
-                            Direction::Right,
-                        )?;
-                        push_face_axis(
-                            &mut vertices,
-                            &mut indices,
-                            k as u32,
-                            i,
-                            j,
-                            k,
-                            visible_front,
-                            Direction::Front,
-                        )?;
-                        push_face_axis(
-                            &mut vertices,
-                            &mut indices,
-                            k as u32,
-                            i,
-                            j,
-                            k,
-                            visible_back,
-                            Direction::Back,
-                        )?;
-                        push_face_axis(
-                            &mut vertices,
-                            &mut indices,
-                            k as u32,
-                            i,
-                            k,
-                            j,
-                            visible_top,
-                            Direction::Up,
-                        )?;
-                        push_face_axis(
-                            &mut vertices,
-                            &mut indices,
-                            k as u32,
-                            i,
-                            k,
-                            j,
-                            visible_bottom,
-                            Direction::Down,
-                        )?;
-                    }
-
-                    Ok(())
-                };
-
-                if let Err(e) = success {
-                    eprintln!("Error: {}", e);
+                let [(x_axis, x_occluders), (y_axis, y_occluders), (z_axis, z_occluders)] =
+                    layer.axes(chunk, i, j);
+
+                let (visible_right, visible_left) = line_axis(x_axis, x_occluders);
+                let (visible_top, visible_bottom) = line_axis(y_axis, y_occluders);
+                let (visible_front, visible_back) = line_axis(z_axis, z_occluders);
+
+                for k in 0..CHUNK_SIZE {
+                    push_face_axis(&mut mesh, k as u32, k, i, j, visible_left, Direction::Left);
+                    push_face_axis(
+                        &mut mesh,
+                        k as u32,
+                        k,
+                        i,
+                        j,
+                        visible_right,
+                        Direction::Right,
+                    );
+                    push_face_axis(
+                        &mut mesh,
+                        k as u32,
+                        i,
+                        j,
+                        k,
+                        visible_front,
+                        Direction::Front,
+                    );
+                    push_face_axis(&mut mesh, k as u32, i, j, k, visible_back, Direction::Back);
+                    push_face_axis(&mut mesh, k as u32, i, k, j, visible_top, Direction::Up);
+                    push_face_axis(
+                        &mut mesh,
+                        k as u32,
+                        i,
+                        k,
+                        j,
+                        visible_bottom,
+                        Direction::Down,
+                    );
                 }
             }
         }
 
-        Ok(Self { vertices, indices })
+        Ok(mesh)
     }
 }
@@ -4,21 +4,21 @@ use bevy::prelude::*;
 use bevy::render::mesh::MeshVertexBufferLayoutRef;
 use bevy::render::render_asset::RenderAssetUsages;
 use bevy::render::render_resource::{
-    AsBindGroup, Extent3d, PolygonMode, RenderPipelineDescriptor, ShaderRef,
+    AsBindGroup, Extent3d, PolygonMode, RenderPipelineDescriptor, ShaderDefVal, ShaderRef,
     SpecializedMeshPipelineError, TextureDimension, TextureFormat,
 };
 use bevy::render::{mesh::MeshVertexAttribute, render_resource::VertexFormat};
 
-use voxel::world::chunk::{Chunk, CHUNK_SIZE};
+use voxel::world::chunk::{Chunk, CHUNK_SIZE, PADDED_SIZE};
 
 #[derive(Copy, Clone)]
 pub enum Direction {
-    Left,
-    Right,
-    Down,
-    Up,
-    Back,
-    Front,
+    Left = 0,
+    Right = 1,
+    Down = 2,
+    Up = 3,
+    Back = 4,
+    Front = 5,
 }
 
 pub struct Quad {
@@ -29,11 +29,23 @@ pub struct Quad {
 pub const ATTRIBUTE_VOXEL: MeshVertexAttribute =
     MeshVertexAttribute::new("VoxelVertex", 91010550917, VertexFormat::Uint32);
 
+// Block and health of the face in the same layout as the chunk blocks, 0 when the shader has to sample the chunk texture
+pub const ATTRIBUTE_VOXEL_FACE: MeshVertexAttribute =
+    MeshVertexAttribute::new("VoxelFace", 91010550918, VertexFormat::Uint32);
+
 #[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
 pub struct ChunkMaterial {
     #[texture(0, dimension = "3d")]
     #[sampler(1)]
     pub image_3d: Handle<Image>,
+
+    // Light of the chunk with its apron, so faces on the border can read the light of the neighbours
+    #[texture(2, dimension = "3d")]
+    #[sampler(3)]
+    pub light_3d: Handle<Image>,
+
+    // Blend for the transparent faces of the chunk
+    pub alpha_mode: AlphaMode,
 }
 
 impl ChunkMaterial {
@@ -50,8 +62,31 @@ impl ChunkMaterial {
             RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
         );
 
+        let light = Image::new(
+            Extent3d {
+                width: PADDED_SIZE as u32,
+                height: PADDED_SIZE as u32,
+                depth_or_array_layers: PADDED_SIZE as u32,
+            },
+            TextureDimension::D3,
+            chunk.light(),
+            TextureFormat::R8Unorm,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        );
+
         Self {
             image_3d: images.add(image),
+            light_3d: images.add(light),
+            alpha_mode: AlphaMode::Opaque,
+        }
+    }
+
+    // Same textures, but blended. Bevy sorts the blended meshes by the distance of their entity only, and the quads
+    // inside a mesh are drawn in meshing order, so overlapping transparent faces can blend in the wrong order.
+    pub fn transparent(&self) -> Self {
+        Self {
+            alpha_mode: AlphaMode::Blend,
+            ..self.clone()
         }
     }
 
@@ -59,6 +94,14 @@ impl ChunkMaterial {
         if let Some(image) = images.get_mut(&self.image_3d) {
             image.data = chunk.blocks();
         }
+
+        self.update_light(chunk, images);
+    }
+
+    pub fn update_light(&mut self, chunk: &Chunk, images: &mut ResMut<Assets<Image>>) {
+        if let Some(image) = images.get_mut(&self.light_3d) {
+            image.data = chunk.light();
+        }
     }
 }
 
@@ -70,22 +113,101 @@ impl Material for ChunkMaterial {
         "shaders/chunk.wgsl".into()
     }
 
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
     fn specialize(
         _pipeline: &MaterialPipeline<Self>,
         descriptor: &mut RenderPipelineDescriptor,
         layout: &MeshVertexBufferLayoutRef,
         _key: MaterialPipelineKey<Self>,
     ) -> Result<(), SpecializedMeshPipelineError> {
-        let vertex_layout = layout
-            .0
-            .get_layout(&[ATTRIBUTE_VOXEL.at_shader_location(0)])?;
+        let vertex_layout = layout.0.get_layout(&[
+            ATTRIBUTE_VOXEL.at_shader_location(0),
+            ATTRIBUTE_VOXEL_FACE.at_shader_location(1),
+        ])?;
         descriptor.primitive.polygon_mode = PolygonMode::Fill;
         descriptor.vertex.buffers = vec![vertex_layout];
+        descriptor
+            .vertex
+            .shader_defs
+            .push(ShaderDefVal::UInt("CHUNK_SIZE".into(), CHUNK_SIZE as u32));
         Ok(())
     }
 }
 
+impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::Left,
+        Direction::Right,
+        Direction::Down,
+        Direction::Up,
+        Direction::Back,
+        Direction::Front,
+    ];
+
+    pub fn index(&self) -> u32 {
+        *self as u32
+    }
+
+    pub fn normal(&self) -> IVec3 {
+        match self {
+            Direction::Left => IVec3::NEG_X,
+            Direction::Right => IVec3::X,
+            Direction::Down => IVec3::NEG_Y,
+            Direction::Up => IVec3::Y,
+            Direction::Back => IVec3::NEG_Z,
+            Direction::Front => IVec3::Z,
+        }
+    }
+
+    // Corners of the face in winding order, true means the far side of the quad on that axis
+    fn corners(&self) -> [BVec3; 4] {
+        match self {
+            Direction::Left => [
+                BVec3::new(false, false, false),
+                BVec3::new(false, false, true),
+                BVec3::new(false, true, true),
+                BVec3::new(false, true, false),
+            ],
+            Direction::Right => [
+                BVec3::new(true, false, true),
+                BVec3::new(true, false, false),
+                BVec3::new(true, true, false),
+                BVec3::new(true, true, true),
+            ],
+            Direction::Down => [
+                BVec3::new(false, false, true),
+                BVec3::new(false, false, false),
+                BVec3::new(true, false, false),
+                BVec3::new(true, false, true),
+            ],
+            Direction::Up => [
+                BVec3::new(false, true, false),
+                BVec3::new(false, true, true),
+                BVec3::new(true, true, true),
+                BVec3::new(true, true, false),
+            ],
+            Direction::Back => [
+                BVec3::new(false, false, false),
+                BVec3::new(false, true, false),
+                BVec3::new(true, true, false),
+                BVec3::new(true, false, false),
+            ],
+            Direction::Front => [
+                BVec3::new(false, true, true),
+                BVec3::new(false, false, true),
+                BVec3::new(true, false, true),
+                BVec3::new(true, true, true),
+            ],
+        }
+    }
+}
+
 impl Quad {
+    // Vertex layout : 6bits for each coordinate, 3bits for the offsets toward the center of the voxel,
+    // 3bits for the direction and 2bits for the ambient occlusion in the bits 28 and 29. The bits 24 to 27, 30 and 31 are free.
     pub fn from_direction(
         direction: Direction,
         vertices_offset: usize,
@@ -100,259 +222,93 @@ impl Quad {
         let y1 = (y + size.y).clamp(0, CHUNK_SIZE as u32);
         let z1 = (z + size.z).clamp(0, CHUNK_SIZE as u32);
 
-        let vertices = match direction {
-            Direction::Left => {
-                vec![
-                    1 << 22
-                        | 1 << 20
-                        | 0 << 18
-                        | 1 << 17
-                        | 1 << 16
-                        | 1 << 15
-                        | z << 10
-                        | y << 5
-                        | x,
-                    1 << 22
-                        | 1 << 20
-                        | 0 << 18
-                        | 0 << 17
-                        | 1 << 16
-                        | 1 << 15
-                        | z1 << 10
-                        | y << 5
-                        | x,
-                    1 << 22
-                        | 1 << 20
-                        | 0 << 18
-                        | 0 << 17
-                        | 0 << 16
-                        | 1 << 15
-                        | z1 << 10
-                        | y1 << 5
-                        | x,
-                    1 << 22
-                        | 1 << 20
-                        | 0 << 18
-                        | 1 << 17
-                        | 0 << 16
-                        | 1 << 15
-                        | z << 10
-                        | y1 << 5
-                        | x,
-                ]
-            }
-            Direction::Right => {
-                vec![
-                    1 << 22
-                        | 1 << 20
-                        | 2 << 18
-                        | 0 << 17
-                        | 1 << 16
-                        | 0 << 15
-                        | z1 << 10
-                        | y << 5
-                        | x1,
-                    1 << 22
-                        | 1 << 20
-                        | 2 << 18
-                        | 1 << 17
-                        | 1 << 16
-                        | 0 << 15
-                        | z << 10
-                        | y << 5
-                        | x1,
-                    1 << 22
-                        | 1 << 20
-                        | 2 << 18
-                        | 1 << 17
-                        | 0 << 16
-                        | 0 << 15
-                        | z << 10
-                        | y1 << 5
-                        | x1,
-                    1 << 22
-                        | 1 << 20
-                        | 2 << 18
-                        | 0 << 17
-                        | 0 << 16
-                        | 0 << 15
-                        | z1 << 10
-                        | y1 << 5
-                        | x1,
-                ]
-            }
-            Direction::Down => {
-                vec![
-                    1 << 22
-                        | 0 << 20
-                        | 1 << 18
-                        | 0 << 17
-                        | 1 << 16
-                        | 1 << 15
-                        | z1 << 10
-                        | y << 5
-                        | x,
-                    1 << 22
-                        | 0 << 20
-                        | 1 << 18
-                        | 1 << 17
-                        | 1 << 16
-                        | 1 << 15
-                        | z << 10
-                        | y << 5
-                        | x,
-                    1 << 22
-                        | 0 << 20
-                        | 1 << 18
-                        | 1 << 17
-                        | 1 << 16
-                        | 0 << 15
-                        | z << 10
-                        | y << 5
-                        | x1,
-                    1 << 22
-                        | 0 << 20
-                        | 1 << 18
-                        | 0 << 17
-                        | 1 << 16
-                        | 0 << 15
-                        | z1 << 10
-                        | y << 5
-                        | x1,
-                ]
-            }
-            Direction::Up => {
-                vec![
-                    1 << 22
-                        | 2 << 20
-                        | 1 << 18
-                        | 1 << 17
-                        | 0 << 16
-                        | 1 << 15
-                        | z << 10
-                        | y1 << 5
-                        | x,
-                    1 << 22
-                        | 2 << 20
-                        | 1 << 18
-                        | 0 << 17
-                        | 0 << 16
-                        | 1 << 15
-                        | z1 << 10
-                        | y1 << 5
-                        | x,
-                    1 << 22
-                        | 2 << 20
-                        | 1 << 18
-                        | 0 << 17
-                        | 0 << 16
-                        | 0 << 15
-                        | z1 << 10
-                        | y1 << 5
-                        | x1,
-                    1 << 22
-                        | 2 << 20
-                        | 1 << 18
-                        | 1 << 17
-                        | 0 << 16
-                        | 0 << 15
-                        | z << 10
-                        | y1 << 5
-                        | x1,
-                ]
-            }
-            Direction::Back => {
-                vec![
-                    0 << 22
-                        | 1 << 20
-                        | 1 << 18
-                        | 1 << 17
-                        | 1 << 16
-                        | 1 << 15
-                        | z << 10
-                        | y << 5
-                        | x,
-                    0 << 22
-                        | 1 << 20
-                        | 1 << 18
-                        | 1 << 17
-                        | 0 << 16
-                        | 1 << 15
-                        | z << 10
-                        | y1 << 5
-                        | x,
-                    0 << 22
-                        | 1 << 20
-                        | 1 << 18
-                        | 1 << 17
-                        | 0 << 16
-                        | 0 << 15
-                        | z << 10
-                        | y1 << 5
-                        | x1,
-                    0 << 22
-                        | 1 << 20
-                        | 1 << 18
-                        | 1 << 17
-                        | 1 << 16
-                        | 0 << 15
-                        | z << 10
-                        | y << 5
-                        | x1,
-                ]
-            }
-            Direction::Front => {
-                vec![
-                    2 << 22
-                        | 1 << 20
-                        | 1 << 18
-                        | 0 << 17
-                        | 0 << 16
-                        | 1 << 15
-                        | z1 << 10
-                        | y1 << 5
-                        | x,
-                    2 << 22
-                        | 1 << 20
-                        | 1 << 18
-                        | 0 << 17
-                        | 1 << 16
-                        | 1 << 15
-                        | z1 << 10
-                        | y << 5
-                        | x,
-                    2 << 22
-                        | 1 << 20
-                        | 1 << 18
-                        | 0 << 17
-                        | 1 << 16
-                        | 0 << 15
-                        | z1 << 10
-                        | y << 5
-                        | x1,
-                    2 << 22
-                        | 1 << 20
-                        | 1 << 18
-                        | 0 << 17
-                        | 0 << 16
-                        | 0 << 15
-                        | z1 << 10
-                        | y1 << 5
-                        | x1,
-                ]
-            }
-        };
+        let vertices = direction
+            .corners()
+            .iter()
+            .map(|corner| {
+                let x = if corner.x { x1 } else { x };
+                let y = if corner.y { y1 } else { y };
+                let z = if corner.z { z1 } else { z };
+
+                direction.index() << 21
+                    | (!corner.z as u32) << 20
+                    | (!corner.y as u32) << 19
+                    | (!corner.x as u32) << 18
+                    | z << 12
+                    | y << 6
+                    | x
+            })
+            .collect();
 
         Self {
             vertices,
             indices: vec![
-                0 + vertices_offset as u32,
+                vertices_offset as u32,
                 1 + vertices_offset as u32,
                 2 + vertices_offset as u32,
-                0 + vertices_offset as u32,
+                vertices_offset as u32,
                 2 + vertices_offset as u32,
                 3 + vertices_offset as u32,
             ],
         }
     }
+    // Occlusion of each corner packed on 2bits in the same order as the vertices, as returned by ambient_occlusion
+    pub fn with_occlusion(mut self, occlusion: u8) -> Self {
+        for (corner, vertex) in self.vertices.iter_mut().enumerate() {
+            *vertex |= (((occlusion >> (corner * 2)) & 0b11) as u32) << 28;
+        }
+
+        // Split the quad along the most occluded diagonal so the interpolation stays symmetric
+        let corner = |index: usize| (occlusion >> (index * 2)) & 0b11;
+        if corner(0) + corner(2) < corner(1) + corner(3) {
+            let offset = self.indices[0];
+
+            self.indices = vec![
+                1 + offset,
+                2 + offset,
+                3 + offset,
+                1 + offset,
+                3 + offset,
+                offset,
+            ];
+        }
+
+        self
+    }
+}
+
+// Standard vertex ambient occlusion from the voxels in front of the face, 0 is unoccluded and 3 fully occluded.
+// The chunk apron makes the occupancy of the neighbours available on the borders.
+pub fn ambient_occlusion(chunk: &Chunk, pos: IVec3, direction: Direction) -> u8 {
+    let front = pos + direction.normal();
+    let tangents = direction.normal().abs().cmpeq(IVec3::ZERO);
+
+    let mut occlusion = 0;
+
+    for (index, corner) in direction.corners().iter().enumerate() {
+        let sign = IVec3::select(*corner, IVec3::ONE, IVec3::NEG_ONE);
+
+        // Split the corner offset on the two tangent axes of the face
+        let mut sides = [IVec3::ZERO; 2];
+        let mut count = 0;
+        for axis in 0..3 {
+            if tangents.test(axis) {
+                sides[count][axis] = sign[axis];
+                count += 1;
+            }
+        }
+
+        let side1 = chunk.is_opaque(front + sides[0]);
+        let side2 = chunk.is_opaque(front + sides[1]);
+        let diagonal = chunk.is_opaque(front + sides[0] + sides[1]);
+
+        let value = match side1 && side2 {
+            true => 3,
+            false => side1 as u8 + side2 as u8 + diagonal as u8,
+        };
+
+        occlusion |= value << (index * 2);
+    }
+
+    occlusion
 }
@@ -0,0 +1,230 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input::PlayerInput,
+    weapon::{Weapon, WeaponDefinition},
+};
+
+// Relative to the asset folder of the game
+pub const ARSENAL_PATH: &str = "weapons.ron";
+
+// Every weapon the players can pick, loaded from an asset so they can be tuned while the game runs
+#[derive(Debug, Clone, Resource)]
+pub struct Arsenal {
+    pub weapons: Vec<WeaponDefinition>,
+
+    pub handle: Handle<WeaponList>,
+}
+
+#[derive(Debug, Clone, Asset, TypePath, Deserialize)]
+#[serde(transparent)]
+pub struct WeaponList(pub Vec<WeaponDefinition>);
+
+#[derive(Default)]
+pub struct WeaponListLoader;
+
+// Weapons a player picked at spawn, by name in the arsenal
+#[derive(Debug, Clone, PartialEq, Component, Serialize, Deserialize)]
+pub struct Loadout {
+    pub weapons: Vec<String>,
+    #[serde(skip)]
+    pub selected: usize,
+    // State of the weapons put away by slot, so switching back keeps their ammo and reload
+    #[serde(skip)]
+    pub holstered: Vec<Option<Weapon>>,
+}
+
+impl AssetLoader for WeaponListLoader {
+    type Asset = WeaponList;
+    type Settings = ();
+    type Error = eyre::Report;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> eyre::Result<WeaponList> {
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).await?;
+
+        Ok(ron::de::from_bytes(&content)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["weapons.ron"]
+    }
+}
+
+// The built-in weapons are used until the asset is loaded, and when it is missing or broken
+impl Default for Arsenal {
+    fn default() -> Self {
+        Self {
+            weapons: vec![
+                WeaponDefinition::rifle(),
+                WeaponDefinition::shotgun(),
+                WeaponDefinition::rocket_launcher(),
+                WeaponDefinition::grenade_launcher(),
+                WeaponDefinition::bow(),
+            ],
+            handle: Handle::default(),
+        }
+    }
+}
+
+impl Arsenal {
+    pub fn get(&self, name: &str) -> Option<&WeaponDefinition> {
+        self.weapons.iter().find(|weapon| weapon.name == name)
+    }
+}
+
+impl Default for Loadout {
+    fn default() -> Self {
+        Self::new([
+            "Rifle",
+            "Shotgun",
+            "Rocket launcher",
+            "Grenade launcher",
+            "Bow",
+        ])
+    }
+}
+
+impl Loadout {
+    pub fn new(weapons: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            weapons: weapons.into_iter().map(Into::into).collect(),
+            selected: 0,
+            holstered: Vec::new(),
+        }
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        self.weapons.get(self.selected).map(String::as_str)
+    }
+
+    pub fn cycle(&mut self) {
+        if !self.weapons.is_empty() {
+            self.selected = (self.selected + 1) % self.weapons.len();
+        }
+    }
+
+    pub fn holster(&mut self, slot: usize, weapon: Weapon) {
+        if slot >= self.weapons.len() {
+            return;
+        }
+
+        if self.holstered.len() < self.weapons.len() {
+            self.holstered.resize(self.weapons.len(), None);
+        }
+
+        self.holstered[slot] = Some(weapon);
+    }
+
+    // Take back the weapon put away in the slot, if it is still the one the slot names
+    pub fn draw(&mut self, slot: usize) -> Option<Weapon> {
+        let weapon = self.holstered.get_mut(slot)?.take()?;
+        let name = self.weapons.get(slot)?;
+
+        (weapon.definition.name == *name).then_some(weapon)
+    }
+
+    // Forget the state of every slot, the weapons are drawn fresh from the arsenal
+    pub fn reset(&mut self) {
+        self.holstered.clear();
+    }
+}
+
+pub fn load_arsenal(asset_server: Res<AssetServer>, mut arsenal: ResMut<Arsenal>) {
+    arsenal.handle = asset_server.load(ARSENAL_PATH);
+}
+
+// Take the weapons of the asset once loaded and each time it changes, the weapons in hand take the new
+// definitions. A broken file is reported by the asset server and the current weapons are kept until it is fixed.
+pub fn reload_arsenal(
+    mut events: EventReader<AssetEvent<WeaponList>>,
+    lists: Res<Assets<WeaponList>>,
+    mut arsenal: ResMut<Arsenal>,
+    mut weapons: Query<&mut Weapon>,
+    mut loadouts: Query<&mut Loadout>,
+) {
+    for event in events.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = *event
+        else {
+            continue;
+        };
+
+        if id != arsenal.handle.id() {
+            continue;
+        }
+
+        let Some(list) = lists.get(id) else {
+            continue;
+        };
+
+        arsenal.weapons = list.0.clone();
+
+        for mut weapon in &mut weapons {
+            refresh_weapon(&arsenal, &mut weapon);
+        }
+
+        for mut loadout in &mut loadouts {
+            for weapon in loadout.holstered.iter_mut().flatten() {
+                refresh_weapon(&arsenal, weapon);
+            }
+        }
+    }
+}
+
+fn refresh_weapon(arsenal: &Arsenal, weapon: &mut Weapon) {
+    let Some(definition) = arsenal.get(&weapon.definition.name) else {
+        return;
+    };
+
+    if *definition != weapon.definition {
+        weapon.definition = definition.clone();
+        weapon.ammo = weapon.ammo.min(definition.magazine);
+    }
+}
+
+// Give the players the selected weapon of their loadout, the weapon in hand is put away with its state
+pub fn equip_loadouts(
+    mut commands: Commands,
+    arsenal: Res<Arsenal>,
+    mut players: Query<(Entity, &mut Loadout, &PlayerInput, Option<&Weapon>)>,
+) {
+    for (entity, mut loadout, input, weapon) in &mut players {
+        let previous = loadout.selected;
+
+        if input.next_weapon {
+            loadout.cycle();
+        }
+
+        let Some(name) = loadout.selected().map(str::to_owned) else {
+            continue;
+        };
+
+        if weapon.is_some_and(|weapon| weapon.definition.name == name) {
+            continue;
+        }
+
+        if let Some(weapon) = weapon {
+            if loadout.selected != previous {
+                loadout.holster(previous, weapon.clone());
+            }
+        }
+
+        let slot = loadout.selected;
+        let weapon = loadout
+            .draw(slot)
+            .or_else(|| arsenal.get(&name).cloned().map(Weapon::new));
+
+        if let Some(weapon) = weapon {
+            commands.entity(entity).insert(weapon);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use bevy::prelude::*;
+use voxel::world::{
+    chunk::Chunk,
+    coords::BlockPos,
+    gravity::{GRAVITY, MAX_FALL_SPEED},
+    VoxelWorld,
+};
+
+use crate::input::PlayerInput;
+
+pub const HALF_WIDTH: f32 = 0.3;
+pub const HEIGHT: f32 = 1.8;
+pub const CROUCH_HEIGHT: f32 = 1.2;
+
+// Distance between the eyes, where the transform of the character is, and the top of its box
+pub const EYE_OFFSET: f32 = 0.15;
+
+pub const WALK_SPEED: f32 = 6.0;
+pub const CROUCH_SPEED: f32 = 2.5;
+pub const JUMP_SPEED: f32 = 8.0;
+pub const AIR_CONTROL: f32 = 0.1;
+
+// Characters walk up single blocks without jumping
+pub const STEP_HEIGHT: f32 = 1.0;
+
+const EPSILON: f32 = 1e-4;
+
+// Character walking on the voxels of the world, its box hangs under its transform
+#[derive(Debug, Default, Clone, Component)]
+pub struct CharacterController {
+    pub velocity: Vec3,
+    pub grounded: bool,
+    pub crouching: bool,
+}
+
+// Unloaded voxels block the characters so they don't fall out of the world
+fn is_solid(world: &VoxelWorld, chunks: &Query<&Chunk>, pos: BlockPos) -> bool {
+    world
+        .get_block(chunks, pos)
+        .is_none_or(|(block, _)| block.is_solid())
+}
+
+fn voxel_range(min: f32, max: f32) -> std::ops::RangeInclusive<i32> {
+    (min + EPSILON).floor() as i32..=(max - EPSILON).floor() as i32
+}
+
+fn is_blocked(world: &VoxelWorld, chunks: &Query<&Chunk>, min: Vec3, max: Vec3) -> bool {
+    voxel_range(min.y, max.y).any(|y| {
+        voxel_range(min.z, max.z).any(|z| {
+            voxel_range(min.x, max.x).any(|x| is_solid(world, chunks, BlockPos::new(x, y, z)))
+        })
+    })
+}
+
+// Move the box along one axis and return the distance travelled before touching a voxel
+fn sweep(
+    world: &VoxelWorld,
+    chunks: &Query<&Chunk>,
+    min: Vec3,
+    max: Vec3,
+    axis: usize,
+    delta: f32,
+) -> f32 {
+    if delta == 0.0 {
+        return 0.0;
+    }
+
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+    let layer_blocked = |layer: i32| {
+        voxel_range(min[u], max[u]).any(|a| {
+            voxel_range(min[v], max[v]).any(|b| {
+                let mut pos = IVec3::ZERO;
+                pos[axis] = layer;
+                pos[u] = a;
+                pos[v] = b;
+
+                is_solid(world, chunks, BlockPos(pos))
+            })
+        })
+    };
+
+    // Only the layers of voxels entered during the move are checked
+    if delta > 0.0 {
+        let first = (max[axis] - EPSILON).ceil() as i32;
+        let last = (max[axis] + delta).ceil() as i32 - 1;
+
+        for layer in first..=last {
+            if layer_blocked(layer) {
+                return (layer as f32 - max[axis]).clamp(0.0, delta);
+            }
+        }
+    } else {
+        let first = (min[axis] + EPSILON).floor() as i32 - 1;
+        let last = (min[axis] + delta).floor() as i32;
+
+        for layer in (last..=first).rev() {
+            if layer_blocked(layer) {
+                return ((layer + 1) as f32 - min[axis]).clamp(delta, 0.0);
+            }
+        }
+    }
+
+    delta
+}
+
+impl CharacterController {
+    pub fn height(&self) -> f32 {
+        match self.crouching {
+            true => CROUCH_HEIGHT,
+            false => HEIGHT,
+        }
+    }
+
+    // Position of the feet of a character placed at the transform
+    pub fn feet(&self, transform: &Transform) -> Vec3 {
+        transform.translation - Vec3::Y * (self.height() - EYE_OFFSET)
+    }
+
+    fn bounds(feet: Vec3, height: f32) -> (Vec3, Vec3) {
+        (
+            feet - Vec3::new(HALF_WIDTH, 0.0, HALF_WIDTH),
+            feet + Vec3::new(HALF_WIDTH, height, HALF_WIDTH),
+        )
+    }
+
+    // Horizontal move of the box from the feet, returns the new feet
+    fn slide(
+        world: &VoxelWorld,
+        chunks: &Query<&Chunk>,
+        mut feet: Vec3,
+        height: f32,
+        motion: Vec3,
+    ) -> Vec3 {
+        for axis in [0, 2] {
+            let (min, max) = Self::bounds(feet, height);
+
+            feet[axis] += sweep(world, chunks, min, max, axis, motion[axis]);
+        }
+
+        feet
+    }
+
+    // Move the character for one fixed step
+    pub fn step(
+        &mut self,
+        transform: &mut Transform,
+        input: &PlayerInput,
+        world: &VoxelWorld,
+        chunks: &Query<&Chunk>,
+        delta: f32,
+    ) {
+        let mut feet = self.feet(transform);
+
+        // Standing up needs room above the head
+        if input.crouch {
+            self.crouching = true;
+        } else if self.crouching {
+            let (min, max) = Self::bounds(feet, HEIGHT);
+
+            self.crouching = is_blocked(world, chunks, min, max);
+        }
+
+        let height = self.height();
+
+        let forward = transform
+            .forward()
+            .as_vec3()
+            .with_y(0.0)
+            .normalize_or_zero();
+        let right = transform.right().as_vec3().with_y(0.0).normalize_or_zero();
+
+        let speed = match self.crouching {
+            true => CROUCH_SPEED,
+            false => WALK_SPEED,
+        };
+
+        let wish =
+            (right * input.movement.x + forward * input.movement.y).clamp_length_max(1.0) * speed;
+
+        let control = match self.grounded {
+            true => 1.0,
+            false => AIR_CONTROL,
+        };
+
+        self.velocity.x += (wish.x - self.velocity.x) * control;
+        self.velocity.z += (wish.z - self.velocity.z) * control;
+
+        if input.jump && self.grounded {
+            self.velocity.y = JUMP_SPEED;
+        }
+
+        self.velocity.y = (self.velocity.y - GRAVITY * delta).max(-MAX_FALL_SPEED);
+
+        let motion = self.velocity * delta;
+
+        let mut moved = Self::slide(world, chunks, feet, height, motion);
+
+        // Blocked on the ground, try to go over the obstacle and keep it if it goes further
+        let wanted = motion.with_y(0.0).length();
+        if self.grounded && (moved - feet).length() + EPSILON < wanted {
+            let (min, max) = Self::bounds(feet, height);
+            let up = sweep(world, chunks, min, max, 1, STEP_HEIGHT);
+
+            let mut stepped = Self::slide(world, chunks, feet + Vec3::Y * up, height, motion);
+
+            let (min, max) = Self::bounds(stepped, height);
+            stepped.y += sweep(world, chunks, min, max, 1, -up);
+
+            if (stepped - feet).with_y(0.0).length() > (moved - feet).length() + EPSILON {
+                moved = stepped;
+            }
+        }
+
+        feet = moved;
+
+        let (min, max) = Self::bounds(feet, height);
+        let vertical = sweep(world, chunks, min, max, 1, motion.y);
+
+        feet.y += vertical;
+
+        self.grounded = motion.y < 0.0 && vertical > motion.y;
+
+        if vertical != motion.y {
+            self.velocity.y = 0.0;
+        }
+
+        transform.translation = feet + Vec3::Y * (height - EYE_OFFSET);
+    }
+}
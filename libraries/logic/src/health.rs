@@ -0,0 +1,320 @@
+use bevy::prelude::*;
+use voxel::world::gravity::MIN_FALL_HEIGHT;
+
+use crate::{
+    arsenal::Loadout,
+    controller::CharacterController,
+    player::{Player, Team},
+    simulation::{SimulationSet, SimulationTick},
+    spawn::{SpawnArea, SpawnPoint, SpawnTerrain},
+    weapon::Weapon,
+};
+
+// Seconds before a dead player comes back, and during which it can't be hurt once it is back
+pub const RESPAWN_TIME: f32 = 5.0;
+pub const SPAWN_INVULNERABILITY: f32 = 3.0;
+
+pub struct HealthManagement;
+
+impl Plugin for HealthManagement {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpawnArea>();
+        app.add_event::<DamageEvent>();
+        app.add_event::<PlayerDied>();
+        app.add_systems(
+            FixedUpdate,
+            (
+                kill_fallen_players,
+                apply_damage,
+                respawn_players,
+                update_invulnerability,
+            )
+                .chain()
+                .in_set(SimulationSet::Damage),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+// Takes a part of the damage until it is worn out
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct Armor {
+    pub current: f32,
+    pub max: f32,
+    // Fraction of the damage taken by the armor
+    pub absorption: f32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HitLocation {
+    Head,
+    #[default]
+    Body,
+    Legs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DamageSource {
+    Shot { shooter: Entity },
+    Explosion { shooter: Entity },
+    // Falling out of the world, it goes through the invulnerability and the armor
+    World,
+}
+
+// Damage before the hit location and the armor are taken into account
+#[derive(Debug, Clone, Event)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub source: DamageSource,
+    pub amount: f32,
+    pub location: HitLocation,
+    pub point: Vec3,
+}
+
+#[derive(Debug, Clone, Event)]
+pub struct PlayerDied {
+    pub player: Entity,
+    pub source: DamageSource,
+}
+
+// Dead players don't move, shoot nor get hit until they respawn, they wait longer when there is no safe place
+#[derive(Debug, Clone, Copy, Component)]
+pub struct Dead {
+    // Players who just joined have no source of death
+    pub source: Option<DamageSource>,
+    // Time before the respawn
+    pub respawn: f32,
+}
+
+// Time left before the player can be hurt
+#[derive(Debug, Clone, Copy, Component)]
+pub struct Invulnerable(pub f32);
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn damage(&mut self, amount: f32) {
+        self.current = (self.current - amount).max(0.0);
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.current > 0.0
+    }
+}
+
+impl Armor {
+    pub fn new(max: f32, absorption: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            absorption,
+        }
+    }
+
+    // Take the part of the damage the armor can, returns what it took
+    pub fn absorb(&mut self, amount: f32) -> f32 {
+        let absorbed = (amount * self.absorption).min(self.current);
+        self.current -= absorbed;
+
+        absorbed
+    }
+}
+
+impl HitLocation {
+    pub fn multiplier(&self) -> f32 {
+        match self {
+            Self::Head => 2.0,
+            Self::Body => 1.0,
+            Self::Legs => 0.75,
+        }
+    }
+}
+
+impl Dead {
+    pub fn new(source: DamageSource) -> Self {
+        Self {
+            source: Some(source),
+            respawn: RESPAWN_TIME,
+        }
+    }
+
+    // New players spawn as soon as a place is found
+    pub fn joined() -> Self {
+        Self {
+            source: None,
+            respawn: 0.0,
+        }
+    }
+}
+
+fn kill_fallen_players(
+    mut events: EventWriter<DamageEvent>,
+    players: Query<(Entity, &Health, &Transform), Without<Dead>>,
+) {
+    for (entity, health, transform) in &players {
+        if transform.translation.y < MIN_FALL_HEIGHT {
+            events.send(DamageEvent {
+                target: entity,
+                source: DamageSource::World,
+                amount: health.max,
+                location: HitLocation::Body,
+                point: transform.translation,
+            });
+        }
+    }
+}
+
+type Victims<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Health,
+        Option<&'static mut Armor>,
+        Has<Invulnerable>,
+    ),
+    Without<Dead>,
+>;
+
+fn apply_damage(
+    mut commands: Commands,
+    mut events: EventReader<DamageEvent>,
+    mut deaths: EventWriter<PlayerDied>,
+    mut players: Victims,
+) {
+    for event in events.read() {
+        let Ok((mut health, armor, invulnerable)) = players.get_mut(event.target) else {
+            continue;
+        };
+
+        // Players killed earlier in the tick take no more damage
+        if !health.is_alive() || (invulnerable && event.source != DamageSource::World) {
+            continue;
+        }
+
+        let mut amount = event.amount;
+
+        // Falling out of the world kills whatever the armor and where the player was hit
+        if event.source != DamageSource::World {
+            amount *= event.location.multiplier();
+
+            if let Some(mut armor) = armor {
+                amount -= armor.absorb(amount);
+            }
+        }
+
+        health.damage(amount);
+
+        if !health.is_alive() {
+            commands
+                .entity(event.target)
+                .insert(Dead::new(event.source));
+
+            deaths.send(PlayerDied {
+                player: event.target,
+                source: event.source,
+            });
+        }
+    }
+}
+
+type DeadPlayers<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut Dead,
+        &'static mut Health,
+        Option<&'static mut Armor>,
+        Option<&'static mut Weapon>,
+        Option<&'static mut Loadout>,
+        Option<&'static mut CharacterController>,
+        Option<&'static Team>,
+        &'static mut Transform,
+    ),
+>;
+
+type LivingPlayers<'w, 's> =
+    Query<'w, 's, (&'static Transform, Option<&'static Team>), (With<Player>, Without<Dead>)>;
+
+#[allow(clippy::too_many_arguments)]
+fn respawn_players(
+    mut commands: Commands,
+    time: Res<Time>,
+    tick: Res<SimulationTick>,
+    area: Res<SpawnArea>,
+    terrain: SpawnTerrain,
+    mut dead: DeadPlayers,
+    points: Query<(&SpawnPoint, &Transform), Without<Dead>>,
+    alive: LivingPlayers,
+) {
+    for (entity, mut state, mut health, armor, weapon, loadout, controller, team, mut transform) in
+        &mut dead
+    {
+        state.respawn = (state.respawn - time.delta_seconds()).max(0.0);
+
+        if state.respawn > 0.0 {
+            continue;
+        }
+
+        let enemies = alive
+            .iter()
+            .filter(|(_, other)| team.is_none() || *other != team)
+            .map(|(transform, _)| transform.translation)
+            .collect::<Vec<_>>();
+
+        let seed = tick.0 ^ entity.to_bits().rotate_left(32);
+
+        // Players wait until a safe place is loaded
+        let Some(position) =
+            terrain.find_spawn(&area, points.iter(), team.copied(), &enemies, seed)
+        else {
+            continue;
+        };
+
+        transform.translation = position;
+
+        *health = Health::new(health.max);
+
+        if let Some(mut armor) = armor {
+            armor.current = armor.max;
+        }
+
+        if let Some(mut weapon) = weapon {
+            *weapon = Weapon::new(weapon.definition.clone());
+        }
+
+        if let Some(mut loadout) = loadout {
+            loadout.reset();
+        }
+
+        if let Some(mut controller) = controller {
+            *controller = CharacterController::default();
+        }
+
+        commands
+            .entity(entity)
+            .remove::<Dead>()
+            .insert(Invulnerable(SPAWN_INVULNERABILITY));
+    }
+}
+
+fn update_invulnerability(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut players: Query<(Entity, &mut Invulnerable)>,
+) {
+    for (entity, mut invulnerable) in &mut players {
+        invulnerable.0 -= time.delta_seconds();
+
+        if invulnerable.0 <= 0.0 {
+            commands.entity(entity).remove::<Invulnerable>();
+        }
+    }
+}
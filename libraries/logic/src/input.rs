@@ -0,0 +1,45 @@
+use bevy::prelude::*;
+
+use crate::player::MovementMode;
+
+// Input of a player for one tick, the only thing the simulation reads from the devices
+#[derive(Debug, Default, Clone, Copy, PartialEq, Component)]
+pub struct PlayerInput {
+    pub tick: u64,
+
+    // Right and forward
+    pub movement: Vec2,
+    // Up and down when flying
+    pub vertical: f32,
+    // Yaw and pitch in radians
+    pub look: Vec2,
+
+    pub jump: bool,
+    pub crouch: bool,
+    pub fire: bool,
+    pub build: bool,
+    pub reload: bool,
+    // Switch to the next weapon of the loadout
+    pub next_weapon: bool,
+
+    // Movement mode asked by the player, applied only if it has the permission
+    pub mode: Option<MovementMode>,
+}
+
+// Input gathered from the devices between two ticks, the look and the requests are kept until the next tick samples them.
+// A shot is kept too so a quick click between two ticks isn't lost, the second field tells if fire is still held.
+#[derive(Debug, Default, Clone, Copy, Component)]
+pub struct PendingInput(pub PlayerInput, pub bool);
+
+impl PendingInput {
+    pub fn sample(&mut self, tick: u64) -> PlayerInput {
+        let input = PlayerInput { tick, ..self.0 };
+
+        self.0.look = Vec2::ZERO;
+        self.0.mode = None;
+        self.0.next_weapon = false;
+        self.0.fire = self.1;
+
+        input
+    }
+}
@@ -1 +1,10 @@
+pub mod arsenal;
+pub mod controller;
+pub mod health;
+pub mod input;
+pub mod look;
 pub mod player;
+pub mod projectile;
+pub mod simulation;
+pub mod spawn;
+pub mod weapon;
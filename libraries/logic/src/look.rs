@@ -0,0 +1,42 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::prelude::*;
+
+// Looking straight up or down is avoided so the view never flips
+pub const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+// Where a player looks, the body only turns with the yaw while the head also follows the pitch
+#[derive(Debug, Default, Clone, Copy, PartialEq, Component)]
+pub struct PlayerLook {
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl PlayerLook {
+    pub fn new(yaw: f32, pitch: f32) -> Self {
+        Self {
+            yaw,
+            pitch: pitch.clamp(-MAX_PITCH, MAX_PITCH),
+        }
+    }
+
+    // Rebuilt from the angles each time so no roll accumulates
+    pub fn turn(&mut self, look: Vec2) {
+        *self = Self::new(
+            (self.yaw + look.x).rem_euclid(std::f32::consts::TAU),
+            self.pitch + look.y,
+        );
+    }
+
+    pub fn body_rotation(&self) -> Quat {
+        Quat::from_rotation_y(self.yaw)
+    }
+
+    pub fn head_rotation(&self) -> Quat {
+        Quat::from_rotation_x(self.pitch)
+    }
+
+    pub fn rotation(&self) -> Quat {
+        self.body_rotation() * self.head_rotation()
+    }
+}
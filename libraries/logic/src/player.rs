@@ -1,4 +1,12 @@
-use bevy::{input::mouse::MouseMotion, prelude::*};
+use bevy::{prelude::*, utils::HashMap};
+use voxel::world::{chunk::Chunk, VoxelWorld};
+
+use crate::{
+    controller::CharacterController, health::Dead, input::PlayerInput, look::PlayerLook,
+    simulation::SimulationSet,
+};
+
+pub const FLY_SPEED: f32 = 50.0;
 
 pub struct PlayerManagement;
 
@@ -8,58 +16,182 @@ pub struct Player;
 #[derive(Debug, Component)]
 pub struct PlayerFocus;
 
+// Players of the same team are not enemies, players without a team are enemies of everyone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Component)]
+pub struct Team(pub u8);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Component)]
+pub enum MovementMode {
+    #[default]
+    Walking,
+    // Flies through the voxels
+    Noclip,
+    // Sees what another player sees
+    Spectator {
+        target: Entity,
+    },
+}
+
+// Movement modes a player may switch to besides walking, given to developers and admins
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Component)]
+pub struct PlayerPermissions {
+    pub noclip: bool,
+    pub spectate: bool,
+}
+
+impl PlayerPermissions {
+    pub fn all() -> Self {
+        Self {
+            noclip: true,
+            spectate: true,
+        }
+    }
+
+    pub fn allows(&self, mode: MovementMode) -> bool {
+        match mode {
+            MovementMode::Walking => true,
+            MovementMode::Noclip => self.noclip,
+            MovementMode::Spectator { .. } => self.spectate,
+        }
+    }
+}
+
 impl Plugin for PlayerManagement {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, move_player);
+        app.add_systems(
+            FixedUpdate,
+            (
+                change_movement_modes,
+                look_players,
+                fly_players,
+                move_characters,
+                follow_targets,
+            )
+                .chain()
+                .in_set(SimulationSet::Logic),
+        );
     }
 }
 
-fn move_player(
-    time: Res<Time>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut mouse_motion: EventReader<MouseMotion>,
-    mut query: Query<&mut Transform, With<PlayerFocus>>,
+fn change_movement_modes(
+    mut players: Query<(
+        Entity,
+        &PlayerInput,
+        &PlayerPermissions,
+        &mut MovementMode,
+        Option<&mut CharacterController>,
+    )>,
+    targets: Query<(), With<Player>>,
 ) {
-    for mut transform in &mut query {
-        for motion in mouse_motion.read() {
-            let yaw = -motion.delta.x * 0.3 * time.delta_seconds();
-            let pitch = -motion.delta.y * 0.2 * time.delta_seconds();
+    for (entity, input, permissions, mut mode, controller) in &mut players {
+        let Some(requested) = input.mode else {
+            continue;
+        };
 
-            transform.rotate_y(yaw);
-            transform.rotate_local_x(pitch);
+        if requested == *mode || !permissions.allows(requested) {
+            continue;
         }
 
-        let mut new_position = Vec3::ZERO;
-
-        let forward = transform.forward();
-        let right = transform.right();
-
-        if keyboard_input.pressed(KeyCode::KeyW) {
-            new_position += forward.as_vec3();
+        if let MovementMode::Spectator { target } = requested {
+            if target == entity || !targets.contains(target) {
+                continue;
+            }
         }
 
-        if keyboard_input.pressed(KeyCode::KeyS) {
-            new_position -= forward.as_vec3();
+        // Players land with no speed left from the previous mode
+        if let Some(mut controller) = controller {
+            *controller = CharacterController::default();
         }
 
-        if keyboard_input.pressed(KeyCode::KeyA) {
-            new_position -= right.as_vec3();
-        }
+        *mode = requested;
+    }
+}
 
-        if keyboard_input.pressed(KeyCode::KeyD) {
-            new_position += right.as_vec3();
+fn look_players(mut players: Query<(&PlayerInput, &mut PlayerLook, &mut Transform)>) {
+    for (input, mut look, mut transform) in &mut players {
+        look.turn(input.look);
+
+        transform.rotation = look.body_rotation();
+    }
+}
+
+fn fly_players(
+    time: Res<Time>,
+    mut players: Query<(&PlayerInput, &PlayerLook, &MovementMode, &mut Transform), Without<Dead>>,
+) {
+    for (input, look, mode, mut transform) in &mut players {
+        if *mode != MovementMode::Noclip {
+            continue;
         }
 
-        if keyboard_input.pressed(KeyCode::Space) {
-            new_position += Vec3::Y;
+        // Flying players go where they look, pitch included
+        let rotation = look.rotation();
+
+        let direction = rotation * Vec3::X * input.movement.x
+            + rotation * Vec3::NEG_Z * input.movement.y
+            + Vec3::Y * input.vertical;
+
+        if let Some(direction) = direction.try_normalize() {
+            transform.translation += direction * FLY_SPEED * time.delta_seconds();
         }
+    }
+}
 
-        if keyboard_input.pressed(KeyCode::ShiftLeft) {
-            new_position -= Vec3::Y;
+fn move_characters(
+    time: Res<Time>,
+    worlds: Query<&VoxelWorld>,
+    chunks: Query<&Chunk>,
+    mut characters: Query<
+        (
+            &mut CharacterController,
+            &PlayerInput,
+            &MovementMode,
+            &mut Transform,
+        ),
+        Without<Dead>,
+    >,
+) {
+    let Ok(world) = worlds.get_single() else {
+        return;
+    };
+
+    for (mut controller, input, mode, mut transform) in &mut characters {
+        if *mode != MovementMode::Walking {
+            continue;
         }
 
-        if let Some(new_position) = new_position.try_normalize() {
-            transform.translation += new_position * 50.0 * time.delta_seconds();
+        controller.step(&mut transform, input, world, &chunks, time.delta_seconds());
+    }
+}
+
+// Spectators take the place and the look of their target, they go back to walking when it leaves
+fn follow_targets(
+    mut players: Query<(Entity, &mut MovementMode, &mut PlayerLook, &mut Transform)>,
+    others: Query<(&PlayerLook, &Transform), Without<MovementMode>>,
+) {
+    let views = players
+        .iter()
+        .map(|(entity, _, look, transform)| (entity, (*look, *transform)))
+        .collect::<HashMap<_, _>>();
+
+    for (_, mut mode, mut look, mut transform) in &mut players {
+        let MovementMode::Spectator { target } = *mode else {
+            continue;
+        };
+
+        let view = views.get(&target).cloned().or_else(|| {
+            others
+                .get(target)
+                .ok()
+                .map(|(look, transform)| (*look, *transform))
+        });
+
+        match view {
+            Some((target_look, target_transform)) => {
+                *look = target_look;
+                *transform = target_transform;
+            }
+            None => *mode = MovementMode::Walking,
         }
     }
 }
@@ -0,0 +1,367 @@
+use bevy::{math::bounding::RayCast3d, prelude::*};
+use serde::{Deserialize, Serialize};
+use voxel::world::{
+    chunk::Chunk,
+    coords::BlockPos,
+    gravity::{GRAVITY, MIN_FALL_HEIGHT},
+    raycast::raycast,
+    VoxelWorld,
+};
+
+use crate::{
+    health::{DamageEvent, DamageSource, HitLocation},
+    simulation::SimulationSet,
+    weapon::{hit_targets, BlockMultipliers, HitTargets, WeaponDefinition},
+};
+
+// Projectiles that never land nor explode are removed after this time
+pub const MAX_PROJECTILE_LIFETIME: f32 = 20.0;
+
+// Projectiles slower than this stop bouncing and rest where they are
+const REST_SPEED: f32 = 1.0;
+
+pub struct Projectiles;
+
+impl Plugin for Projectiles {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Explosion>();
+        app.add_systems(
+            FixedUpdate,
+            update_projectiles.in_set(SimulationSet::Combat),
+        );
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectileDefinition {
+    pub speed: f32,
+    // Fraction of the world gravity applied to the projectile
+    pub gravity: f32,
+    // Fraction of the speed kept when bouncing, projectiles that don't bounce stick where they hit
+    pub bounce: f32,
+    pub explode_on_impact: bool,
+    // Time before exploding on its own
+    pub fuse: Option<f32>,
+    // Projectiles without an explosion only damage what they hit
+    pub explosion_radius: f32,
+}
+
+#[derive(Debug, Clone, Component)]
+pub struct Projectile {
+    pub shooter: Entity,
+    pub definition: ProjectileDefinition,
+    // Taken from the weapon that shot the projectile
+    pub damage: f32,
+    pub block_damage: u8,
+    pub block_multipliers: BlockMultipliers,
+    pub velocity: Vec3,
+    pub lifetime: f32,
+    // Block the projectile is stuck in or lying on
+    pub resting: Option<BlockPos>,
+}
+
+#[derive(Debug, Clone, Event)]
+pub struct Explosion {
+    pub shooter: Entity,
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+enum Impact {
+    None,
+    Block(BlockPos, Vec3),
+    Player(Entity, Vec3),
+}
+
+impl ProjectileDefinition {
+    pub fn rocket() -> Self {
+        Self {
+            speed: 40.0,
+            gravity: 0.0,
+            bounce: 0.0,
+            explode_on_impact: true,
+            fuse: None,
+            explosion_radius: 4.0,
+        }
+    }
+
+    pub fn grenade() -> Self {
+        Self {
+            speed: 20.0,
+            gravity: 1.0,
+            bounce: 0.4,
+            explode_on_impact: false,
+            fuse: Some(2.5),
+            explosion_radius: 5.0,
+        }
+    }
+
+    pub fn arrow() -> Self {
+        Self {
+            speed: 50.0,
+            gravity: 0.5,
+            bounce: 0.0,
+            explode_on_impact: false,
+            fuse: None,
+            explosion_radius: 0.0,
+        }
+    }
+}
+
+impl Projectile {
+    pub fn new(
+        shooter: Entity,
+        weapon: &WeaponDefinition,
+        definition: ProjectileDefinition,
+        direction: Vec3,
+    ) -> Self {
+        Self {
+            shooter,
+            velocity: direction.normalize_or_zero() * definition.speed,
+            definition,
+            damage: weapon.damage,
+            block_damage: weapon.block_damage,
+            block_multipliers: weapon.block_multipliers.clone(),
+            lifetime: 0.0,
+            resting: None,
+        }
+    }
+
+    pub fn is_fused(&self) -> bool {
+        self.definition
+            .fuse
+            .is_some_and(|fuse| self.lifetime >= fuse)
+    }
+}
+
+// Damage the blocks and the players in the radius, the damage falls off linearly from the center
+#[allow(clippy::too_many_arguments)]
+pub fn explode(
+    commands: &mut Commands,
+    world: &mut VoxelWorld,
+    chunks: &Query<&Chunk>,
+    targets: &HitTargets,
+    damages: &mut EventWriter<DamageEvent>,
+    center: Vec3,
+    projectile: &Projectile,
+) {
+    let definition = &projectile.definition;
+    let radius = definition.explosion_radius;
+
+    if radius <= 0.0 {
+        return;
+    }
+
+    let falloff = |distance: f32| (1.0 - distance / radius).clamp(0.0, 1.0);
+
+    let min = BlockPos::from(center - Vec3::splat(radius));
+    let max = BlockPos::from(center + Vec3::splat(radius));
+
+    let blocks = BlockPos::aabb(min, max)
+        .filter_map(|pos| {
+            let (block, _) = world.get_block(chunks, pos)?;
+            let damage = projectile.block_damage as f32 * falloff(pos.center().distance(center));
+
+            Some((
+                pos,
+                projectile
+                    .block_multipliers
+                    .apply(block, damage.round() as u8),
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    world.damage_blocks(commands, chunks, blocks);
+
+    for (target, hitbox, transform) in hit_targets(targets) {
+        let closest = Vec3::from(hitbox.aabb(transform).closest_point(center));
+        let amount = projectile.damage * falloff(closest.distance(center));
+
+        if amount > 0.0 {
+            damages.send(DamageEvent {
+                target,
+                source: DamageSource::Explosion {
+                    shooter: projectile.shooter,
+                },
+                amount,
+                location: HitLocation::Body,
+                point: closest,
+            });
+        }
+    }
+}
+
+// Sweep the segment travelled during the step against the voxels and the other players
+fn sweep(
+    world: &VoxelWorld,
+    chunks: &Query<&Chunk>,
+    targets: &HitTargets,
+    projectile: &Projectile,
+    origin: Vec3,
+    motion: Vec3,
+) -> (Impact, f32, Vec3) {
+    let length = motion.length();
+
+    let Ok(direction) = Dir3::new(motion) else {
+        return (Impact::None, 0.0, Vec3::ZERO);
+    };
+
+    let voxel = raycast(world, chunks, origin, *direction, length);
+    let distance = voxel.map_or(length, |hit| hit.distance);
+
+    let ray = RayCast3d::new(origin, direction, distance);
+
+    let player = hit_targets(targets)
+        .filter(|(target, _, _)| *target != projectile.shooter)
+        .filter_map(|(target, hitbox, transform)| {
+            ray.aabb_intersection_at(&hitbox.aabb(transform))
+                .map(|distance| (target, distance))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    match (player, voxel) {
+        (Some((target, distance)), _) => (
+            Impact::Player(target, origin + *direction * distance),
+            distance,
+            -*direction,
+        ),
+        (None, Some(hit)) => (
+            Impact::Block(hit.pos, hit.point),
+            hit.distance,
+            hit.normal.as_vec3(),
+        ),
+        (None, None) => (Impact::None, length, Vec3::ZERO),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_projectiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut events: EventWriter<Explosion>,
+    mut worlds: Query<&mut VoxelWorld>,
+    chunks: Query<&Chunk>,
+    mut projectiles: Query<(Entity, &mut Projectile, &mut Transform)>,
+    targets: HitTargets,
+    mut damages: EventWriter<DamageEvent>,
+) {
+    let Ok(mut world) = worlds.get_single_mut() else {
+        return;
+    };
+
+    let delta = time.delta_seconds();
+
+    for (entity, mut projectile, mut transform) in &mut projectiles {
+        projectile.lifetime += delta;
+
+        let mut explosion = projectile.is_fused().then_some(transform.translation);
+
+        // Projectiles fall again when the block holding them is destroyed
+        if let Some(pos) = projectile.resting {
+            if !world
+                .get_block(&chunks, pos)
+                .is_some_and(|(block, _)| block.is_solid())
+            {
+                projectile.resting = None;
+                projectile.velocity = Vec3::ZERO;
+            }
+        }
+
+        if explosion.is_none() && projectile.resting.is_none() {
+            projectile.velocity.y -= GRAVITY * projectile.definition.gravity * delta;
+
+            let motion = projectile.velocity * delta;
+            let (impact, distance, normal) = sweep(
+                &world,
+                &chunks,
+                &targets,
+                &projectile,
+                transform.translation,
+                motion,
+            );
+
+            let point = transform.translation + motion.normalize_or_zero() * distance;
+
+            match impact {
+                Impact::None => transform.translation = point,
+                _ if projectile.definition.explode_on_impact => explosion = Some(point),
+                Impact::Player(target, point) => {
+                    if projectile.definition.explosion_radius > 0.0 {
+                        explosion = Some(point);
+                    } else {
+                        if let Ok((_, hitbox, target_transform, _)) = targets.get(target) {
+                            damages.send(DamageEvent {
+                                target,
+                                source: DamageSource::Shot {
+                                    shooter: projectile.shooter,
+                                },
+                                amount: projectile.damage,
+                                location: hitbox.location(target_transform, point),
+                                point,
+                            });
+                        }
+
+                        commands.entity(entity).despawn();
+
+                        continue;
+                    }
+                }
+                Impact::Block(pos, point) if projectile.definition.bounce <= 0.0 => {
+                    // Projectiles that don't bounce stick in the block and damage it
+                    if let Some((block, _)) = world.get_block(&chunks, pos) {
+                        let damage = projectile
+                            .block_multipliers
+                            .apply(block, projectile.block_damage);
+
+                        world.damage_blocks(&mut commands, &chunks, [(pos, damage)]);
+                    }
+
+                    transform.translation = point;
+                    projectile.resting = Some(pos);
+                }
+                // The sweep started inside a block, there is no side to bounce off
+                Impact::Block(..) if normal == Vec3::ZERO => explosion = Some(point),
+                Impact::Block(pos, point) => {
+                    let bounce = projectile.definition.bounce;
+
+                    projectile.velocity = projectile.velocity.reject_from_normalized(normal)
+                        * bounce
+                        - projectile.velocity.project_onto_normalized(normal) * bounce;
+
+                    transform.translation = point + normal * 0.01;
+
+                    if projectile.velocity.length() < REST_SPEED {
+                        projectile.resting = Some(pos);
+                    }
+                }
+            }
+        }
+
+        if let Some(center) = explosion {
+            explode(
+                &mut commands,
+                &mut world,
+                &chunks,
+                &targets,
+                &mut damages,
+                center,
+                &projectile,
+            );
+
+            events.send(Explosion {
+                shooter: projectile.shooter,
+                center,
+                radius: projectile.definition.explosion_radius,
+            });
+
+            commands.entity(entity).despawn();
+        } else if projectile.lifetime > MAX_PROJECTILE_LIFETIME
+            || transform.translation.y < MIN_FALL_HEIGHT
+            || (projectile.resting.is_some()
+                && projectile.definition.fuse.is_none()
+                && projectile.lifetime > MAX_PROJECTILE_LIFETIME / 2.0)
+        {
+            commands.entity(entity).despawn();
+        }
+    }
+}
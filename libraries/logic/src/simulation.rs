@@ -0,0 +1,51 @@
+use bevy::prelude::*;
+
+use crate::input::{PendingInput, PlayerInput};
+
+// Gameplay runs at a fixed rate so that the same inputs always give the same result, on the client and the server
+pub const TICK_RATE: f64 = 64.0;
+
+pub struct Simulation;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemSet)]
+pub enum SimulationSet {
+    // The inputs of the tick are sampled before any gameplay system reads them
+    Input,
+    Logic,
+    // Shots are fired once every player moved
+    Combat,
+    // Damage is applied once every shot and explosion of the tick is known
+    Damage,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Resource)]
+pub struct SimulationTick(pub u64);
+
+impl Plugin for Simulation {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::<Fixed>::from_hz(TICK_RATE));
+        app.init_resource::<SimulationTick>();
+        app.configure_sets(
+            FixedUpdate,
+            (
+                SimulationSet::Input,
+                SimulationSet::Logic,
+                SimulationSet::Combat,
+                SimulationSet::Damage,
+            )
+                .chain(),
+        );
+        app.add_systems(FixedUpdate, sample_input.in_set(SimulationSet::Input));
+    }
+}
+
+fn sample_input(
+    mut tick: ResMut<SimulationTick>,
+    mut players: Query<(&mut PendingInput, &mut PlayerInput)>,
+) {
+    tick.0 += 1;
+
+    for (mut pending, mut input) in &mut players {
+        *input = pending.sample(tick.0);
+    }
+}
@@ -0,0 +1,146 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use voxel::world::{
+    blocks::Block,
+    chunk::{Chunk, TerrainGenerated},
+    coords::BlockPos,
+    terrain_height, VoxelWorld,
+};
+
+use crate::{
+    controller::{EYE_OFFSET, HEIGHT},
+    player::Team,
+    weapon::noise,
+};
+
+// Columns of the spawn area tried each time a player spawns
+pub const SPAWN_CANDIDATES: u64 = 64;
+
+// Distance around the generated height where the surface is looked for, the world may have been edited since
+const SURFACE_SEARCH: i32 = 16;
+
+// Where the players spawn when no spawn point of their team is free
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct SpawnArea {
+    // Center of the area on the horizontal plane
+    pub center: Vec2,
+    pub radius: f32,
+    // Players don't spawn closer than this to an enemy, unless there is no other place
+    pub enemy_distance: f32,
+}
+
+// Place where the players appear, put in the world by the designers, its transform is at the feet
+#[derive(Debug, Default, Clone, Copy, Component)]
+pub struct SpawnPoint {
+    // Spawn points without a team are for everyone
+    pub team: Option<Team>,
+}
+
+#[derive(SystemParam)]
+pub struct SpawnTerrain<'w, 's> {
+    pub worlds: Query<'w, 's, &'static VoxelWorld>,
+    pub chunks: Query<'w, 's, &'static Chunk>,
+    pub generated: Query<'w, 's, (), With<TerrainGenerated>>,
+}
+
+impl Default for SpawnArea {
+    fn default() -> Self {
+        Self {
+            center: Vec2::ZERO,
+            radius: 64.0,
+            enemy_distance: 24.0,
+        }
+    }
+}
+
+impl SpawnTerrain<'_, '_> {
+    // Block whose chunk is loaded and generated, so that the terrain won't appear around the player later
+    fn block(&self, world: &VoxelWorld, pos: BlockPos) -> Option<Block> {
+        let entity = world.chunks.get(&pos.chunk())?;
+
+        if !self.generated.contains(*entity) {
+            return None;
+        }
+
+        world.get_block(&self.chunks, pos).map(|(block, _)| block)
+    }
+
+    // Solid ground with room for a standing player above it, fluids excluded
+    pub fn is_safe(&self, world: &VoxelWorld, ground: BlockPos) -> bool {
+        let block = |height: i32| self.block(world, ground.offset(IVec3::Y * height));
+
+        block(0).is_some_and(|block| block.is_solid())
+            && block(1) == Some(Block::Air)
+            && block(2) == Some(Block::Air)
+    }
+
+    // Highest safe ground of the column, around the height of the generated terrain
+    pub fn find_ground(&self, world: &VoxelWorld, x: i32, z: i32) -> Option<BlockPos> {
+        let height = terrain_height(x, z);
+
+        (height - SURFACE_SEARCH..=height + SURFACE_SEARCH)
+            .rev()
+            .map(|y| BlockPos::new(x, y, z))
+            .find(|ground| self.is_safe(world, *ground))
+    }
+
+    // Eye position of a player spawned at one of the spawn points of its team or on the surface of the
+    // area, as far as possible from the enemies. The seed picks the columns tried in the area.
+    pub fn find_spawn<'a>(
+        &self,
+        area: &SpawnArea,
+        points: impl Iterator<Item = (&'a SpawnPoint, &'a Transform)>,
+        team: Option<Team>,
+        enemies: &[Vec3],
+        seed: u64,
+    ) -> Option<Vec3> {
+        let world = self.worlds.get_single().ok()?;
+
+        let distance = |position: Vec3| {
+            enemies
+                .iter()
+                .map(|enemy| enemy.distance(position))
+                .fold(f32::INFINITY, f32::min)
+        };
+
+        let farthest = |positions: &mut dyn Iterator<Item = Vec3>| {
+            positions
+                .map(|position| (position, distance(position)))
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        };
+
+        let point = farthest(
+            &mut points
+                .filter(|(point, _)| point.team.is_none() || point.team == team)
+                .map(|(_, transform)| BlockPos::from(transform.translation).offset(IVec3::NEG_Y))
+                .filter(|ground| self.is_safe(world, *ground))
+                .map(spawn_position),
+        );
+
+        let surface = farthest(
+            &mut (0..SPAWN_CANDIDATES)
+                .filter_map(|candidate| {
+                    let angle = std::f32::consts::TAU * noise(seed.wrapping_add(candidate * 2));
+                    let radius = area.radius * noise(seed.wrapping_add(candidate * 2 + 1)).sqrt();
+
+                    let column = area.center + Vec2::from_angle(angle) * radius;
+
+                    self.find_ground(world, column.x.floor() as i32, column.y.floor() as i32)
+                })
+                .map(spawn_position),
+        );
+
+        // The spawn points are preferred as long as they are far enough from the enemies
+        let candidates = [point, surface].into_iter().flatten();
+
+        candidates
+            .clone()
+            .find(|(_, distance)| *distance >= area.enemy_distance)
+            .or_else(|| candidates.max_by(|(_, a), (_, b)| a.total_cmp(b)))
+            .map(|(position, _)| position)
+    }
+}
+
+// Eye position of a player standing on the ground
+pub fn spawn_position(ground: BlockPos) -> Vec3 {
+    ground.center() + Vec3::Y * (0.5 + HEIGHT - EYE_OFFSET)
+}
@@ -0,0 +1,520 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    math::bounding::{Aabb3d, RayCast3d},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use voxel::world::{blocks::Block, chunk::Chunk, coords::BlockPos, raycast::raycast, VoxelWorld};
+
+use crate::{
+    arsenal::{
+        equip_loadouts, load_arsenal, reload_arsenal, Arsenal, WeaponList, WeaponListLoader,
+    },
+    controller::{EYE_OFFSET, HALF_WIDTH, HEIGHT},
+    health::{DamageEvent, DamageSource, Dead, HitLocation},
+    input::PlayerInput,
+    look::PlayerLook,
+    player::MovementMode,
+    projectile::{Projectile, ProjectileDefinition},
+    simulation::{SimulationSet, SimulationTick},
+};
+
+pub struct Weapons;
+
+impl Plugin for Weapons {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<WeaponList>();
+        app.init_asset_loader::<WeaponListLoader>();
+        app.init_resource::<Arsenal>();
+        app.add_systems(Startup, load_arsenal);
+        app.add_event::<WeaponHit>();
+        app.add_systems(Update, reload_arsenal);
+        app.add_systems(FixedUpdate, equip_loadouts.in_set(SimulationSet::Logic));
+        app.add_systems(FixedUpdate, fire_weapons.in_set(SimulationSet::Combat));
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FireMode {
+    // One shot each time the trigger is pulled
+    #[default]
+    Semi,
+    // Shoots as long as the trigger is held
+    Auto,
+    // A few shots each time the trigger is pulled, the interval is between the shots of a burst
+    Burst {
+        shots: u32,
+        interval: f32,
+    },
+}
+
+// Multiplier of the block damage for each kind of block, blocks not listed take the base damage
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BlockMultipliers(pub BTreeMap<Block, f32>);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeaponDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub fire_mode: FireMode,
+    // Damage of each pellet to the players, at the center of the explosion for exploding projectiles
+    pub damage: f32,
+    // Multiplier of the damage by distance, as points interpolated linearly
+    #[serde(default)]
+    pub falloff: Vec<(f32, f32)>,
+    // Shots per second
+    pub fire_rate: f32,
+    pub range: f32,
+    // Half angle of the cone the pellets are shot in, in radians
+    pub spread: f32,
+    pub pellets: u32,
+    pub magazine: u32,
+    // Seconds to refill the magazine
+    pub reload_time: f32,
+    // Health removed from the blocks hit by each pellet
+    pub block_damage: u8,
+    #[serde(default)]
+    pub block_multipliers: BlockMultipliers,
+    // Weapons shooting projectiles spawn one per pellet instead of hitting instantly
+    #[serde(default)]
+    pub projectile: Option<ProjectileDefinition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Component)]
+pub struct Weapon {
+    pub definition: WeaponDefinition,
+    // Time before the next shot
+    pub cooldown: f32,
+    pub ammo: u32,
+    // Time before the magazine is refilled, zero when not reloading
+    pub reloading: f32,
+    // Shots left in the current burst
+    pub burst: u32,
+    // Whether the trigger was held on the previous tick
+    pub triggered: bool,
+}
+
+// Box around the transform of a player that the shots hit
+#[derive(Debug, Clone, Copy, Component)]
+pub struct Hitbox {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+// Players that shots and explosions may hit
+pub type HitTargets<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Hitbox,
+        &'static Transform,
+        Option<&'static MovementMode>,
+    ),
+    (Without<Projectile>, Without<Dead>),
+>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HitTarget {
+    Block { pos: BlockPos, block: Block },
+    Player(Entity),
+}
+
+// Sent for every pellet that hits something, for the effects and the scores
+#[derive(Debug, Clone, Event)]
+pub struct WeaponHit {
+    pub shooter: Entity,
+    pub origin: Vec3,
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub target: HitTarget,
+    pub damage: f32,
+}
+
+impl WeaponDefinition {
+    pub fn rifle() -> Self {
+        Self {
+            name: "Rifle".to_string(),
+            fire_mode: FireMode::Auto,
+            damage: 20.0,
+            falloff: vec![(50.0, 1.0), (200.0, 0.5)],
+            fire_rate: 8.0,
+            range: 200.0,
+            spread: 0.01,
+            pellets: 1,
+            magazine: 30,
+            reload_time: 2.0,
+            block_damage: 4,
+            block_multipliers: BlockMultipliers::default(),
+            projectile: None,
+        }
+    }
+
+    pub fn shotgun() -> Self {
+        Self {
+            name: "Shotgun".to_string(),
+            fire_mode: FireMode::Semi,
+            damage: 10.0,
+            falloff: vec![(10.0, 1.0), (40.0, 0.2)],
+            fire_rate: 1.2,
+            range: 40.0,
+            spread: 0.08,
+            pellets: 8,
+            magazine: 6,
+            reload_time: 3.0,
+            block_damage: 3,
+            block_multipliers: BlockMultipliers::default(),
+            projectile: None,
+        }
+    }
+
+    pub fn rocket_launcher() -> Self {
+        Self::launcher(
+            "Rocket launcher",
+            0.8,
+            90.0,
+            30,
+            ProjectileDefinition::rocket(),
+        )
+    }
+
+    pub fn grenade_launcher() -> Self {
+        Self::launcher(
+            "Grenade launcher",
+            1.0,
+            100.0,
+            40,
+            ProjectileDefinition::grenade(),
+        )
+    }
+
+    pub fn bow() -> Self {
+        Self::launcher("Bow", 1.5, 45.0, 2, ProjectileDefinition::arrow())
+    }
+
+    fn launcher(
+        name: &str,
+        fire_rate: f32,
+        damage: f32,
+        block_damage: u8,
+        projectile: ProjectileDefinition,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            fire_mode: FireMode::Semi,
+            damage,
+            falloff: Vec::new(),
+            fire_rate,
+            range: 0.0,
+            spread: 0.0,
+            pellets: 1,
+            magazine: 1,
+            reload_time: 1.5,
+            block_damage,
+            block_multipliers: BlockMultipliers::default(),
+            projectile: Some(projectile),
+        }
+    }
+
+    pub fn falloff_at(&self, distance: f32) -> f32 {
+        let (Some(first), Some(last)) = (self.falloff.first(), self.falloff.last()) else {
+            return 1.0;
+        };
+
+        if distance <= first.0 {
+            return first.1;
+        }
+
+        self.falloff
+            .windows(2)
+            .find(|points| distance < points[1].0)
+            .map(|points| {
+                let ((start, from), (end, to)) = (points[0], points[1]);
+
+                from + (to - from) * (distance - start) / (end - start)
+            })
+            .unwrap_or(last.1)
+    }
+}
+
+impl BlockMultipliers {
+    pub fn apply(&self, block: Block, damage: u8) -> u8 {
+        let multiplier = self.0.get(&block).copied().unwrap_or(1.0);
+
+        (damage as f32 * multiplier)
+            .round()
+            .clamp(0.0, u8::MAX as f32) as u8
+    }
+}
+
+impl Weapon {
+    pub fn new(definition: WeaponDefinition) -> Self {
+        Self {
+            ammo: definition.magazine,
+            definition,
+            cooldown: 0.0,
+            reloading: 0.0,
+            burst: 0,
+            triggered: false,
+        }
+    }
+
+    pub fn reload(&mut self) {
+        if self.reloading == 0.0 && self.ammo < self.definition.magazine {
+            self.reloading = self.definition.reload_time.max(f32::EPSILON);
+            self.burst = 0;
+        }
+    }
+
+    // Advance the reload, returns whether the weapon is still reloading
+    fn update_reload(&mut self, delta: f32) -> bool {
+        if self.reloading == 0.0 {
+            return false;
+        }
+
+        self.reloading = (self.reloading - delta).max(0.0);
+
+        if self.reloading == 0.0 {
+            self.ammo = self.definition.magazine;
+        }
+
+        self.reloading > 0.0
+    }
+
+    // Whether the trigger asks for a shot on this tick, according to the fire mode
+    fn wants_to_fire(&mut self, fire: bool) -> bool {
+        let pulled = fire && !self.triggered;
+        self.triggered = fire;
+
+        match self.definition.fire_mode {
+            FireMode::Semi => pulled,
+            FireMode::Auto => fire,
+            FireMode::Burst { shots, .. } => {
+                if pulled && self.burst == 0 {
+                    self.burst = shots;
+                }
+
+                self.burst > 0
+            }
+        }
+    }
+
+    // Time before the next shot once a shot is fired
+    fn next_cooldown(&mut self) -> f32 {
+        let rate = 1.0 / self.definition.fire_rate.max(f32::EPSILON);
+
+        match self.definition.fire_mode {
+            FireMode::Burst { interval, .. } => {
+                self.burst = self.burst.saturating_sub(1);
+
+                match self.burst {
+                    0 => rate,
+                    _ => interval,
+                }
+            }
+            _ => rate,
+        }
+    }
+}
+
+// Spectators stand at the place of their target without being there
+pub fn hit_targets<'a>(
+    targets: &'a HitTargets,
+) -> impl Iterator<Item = (Entity, &'a Hitbox, &'a Transform)> + 'a {
+    targets
+        .iter()
+        .filter(|(.., mode)| !matches!(mode, Some(MovementMode::Spectator { .. })))
+        .map(|(target, hitbox, transform, _)| (target, hitbox, transform))
+}
+
+impl Hitbox {
+    // Box of a standing character, whose transform is at the eyes
+    pub fn character() -> Self {
+        Self {
+            min: Vec3::new(-HALF_WIDTH, EYE_OFFSET - HEIGHT, -HALF_WIDTH),
+            max: Vec3::new(HALF_WIDTH, EYE_OFFSET, HALF_WIDTH),
+        }
+    }
+
+    pub fn aabb(&self, transform: &Transform) -> Aabb3d {
+        Aabb3d {
+            min: (transform.translation + self.min).into(),
+            max: (transform.translation + self.max).into(),
+        }
+    }
+
+    // Part of the box a point on it belongs to, by height
+    pub fn location(&self, transform: &Transform, point: Vec3) -> HitLocation {
+        let height = (point.y - transform.translation.y - self.min.y) / (self.max.y - self.min.y);
+
+        match height {
+            height if height > 0.8 => HitLocation::Head,
+            height if height < 0.4 => HitLocation::Legs,
+            _ => HitLocation::Body,
+        }
+    }
+}
+
+// Pseudo random number in [0, 1) from a seed, so that the spread is the same each time a tick is simulated
+pub fn noise(seed: u64) -> f32 {
+    let mut x = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^= x >> 31;
+
+    (x >> 40) as f32 / (1u64 << 24) as f32
+}
+
+// Direction of a pellet in the cone around the look
+pub fn spread_direction(look: &PlayerLook, spread: f32, seed: u64) -> Vec3 {
+    let angle = spread * noise(seed).sqrt();
+    let around = std::f32::consts::TAU * noise(seed ^ 0x5555_5555_5555_5555);
+
+    let offset =
+        Quat::from_rotation_y(angle * around.cos()) * Quat::from_rotation_x(angle * around.sin());
+
+    look.rotation() * offset * Vec3::NEG_Z
+}
+
+type Shooters<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut Weapon,
+        &'static PlayerInput,
+        &'static PlayerLook,
+        &'static Transform,
+        Option<&'static MovementMode>,
+    ),
+    Without<Dead>,
+>;
+
+#[allow(clippy::too_many_arguments)]
+fn fire_weapons(
+    mut commands: Commands,
+    time: Res<Time>,
+    tick: Res<SimulationTick>,
+    mut events: EventWriter<WeaponHit>,
+    mut damages: EventWriter<DamageEvent>,
+    mut worlds: Query<&mut VoxelWorld>,
+    chunks: Query<&Chunk>,
+    mut shooters: Shooters,
+    targets: HitTargets,
+) {
+    let Ok(mut world) = worlds.get_single_mut() else {
+        return;
+    };
+
+    let mut block_damages = Vec::new();
+
+    let delta = time.delta_seconds();
+
+    for (shooter, mut weapon, input, look, transform, mode) in &mut shooters {
+        weapon.cooldown = (weapon.cooldown - delta).max(0.0);
+
+        let fire = weapon.wants_to_fire(input.fire);
+
+        if weapon.update_reload(delta) || matches!(mode, Some(MovementMode::Spectator { .. })) {
+            continue;
+        }
+
+        // Pulling the trigger on an empty magazine reloads it
+        if (input.reload && weapon.ammo < weapon.definition.magazine) || (fire && weapon.ammo == 0)
+        {
+            weapon.reload();
+
+            continue;
+        }
+
+        if !fire || weapon.cooldown > 0.0 {
+            continue;
+        }
+
+        weapon.ammo -= 1;
+        weapon.cooldown = weapon.next_cooldown();
+
+        let definition = &weapon.definition;
+        let origin = transform.translation;
+
+        for pellet in 0..definition.pellets {
+            let seed = tick.0 ^ shooter.to_bits().rotate_left(32) ^ ((pellet as u64) << 48);
+            let direction = spread_direction(look, definition.spread, seed);
+
+            if let Some(projectile) = &definition.projectile {
+                commands.spawn((
+                    Projectile::new(shooter, definition, projectile.clone(), direction),
+                    SpatialBundle::from_transform(Transform::from_translation(origin)),
+                ));
+
+                continue;
+            }
+
+            let voxel = raycast(&world, &chunks, origin, direction, definition.range);
+
+            let ray = RayCast3d::new(
+                origin,
+                Dir3::new(direction).unwrap_or(Dir3::NEG_Z),
+                voxel.map_or(definition.range, |hit| hit.distance),
+            );
+
+            let player = hit_targets(&targets)
+                .filter(|(target, _, _)| *target != shooter)
+                .filter_map(|(target, hitbox, transform)| {
+                    ray.aabb_intersection_at(&hitbox.aabb(transform))
+                        .map(|distance| (target, hitbox, transform, distance))
+                })
+                .min_by(|(.., a), (.., b)| a.total_cmp(b));
+
+            let hit = match (player, voxel) {
+                (Some((target, hitbox, target_transform, distance)), _) => {
+                    let damage = definition.damage * definition.falloff_at(distance);
+                    let point = origin + direction * distance;
+
+                    damages.send(DamageEvent {
+                        target,
+                        source: DamageSource::Shot { shooter },
+                        amount: damage,
+                        location: hitbox.location(target_transform, point),
+                        point,
+                    });
+
+                    WeaponHit {
+                        shooter,
+                        origin,
+                        point,
+                        normal: -direction,
+                        target: HitTarget::Player(target),
+                        damage,
+                    }
+                }
+                (None, Some(voxel)) => {
+                    let damage = definition
+                        .block_multipliers
+                        .apply(voxel.block, definition.block_damage);
+
+                    block_damages.push((voxel.pos, damage));
+
+                    WeaponHit {
+                        shooter,
+                        origin,
+                        point: voxel.point,
+                        normal: voxel.normal.as_vec3(),
+                        target: HitTarget::Block {
+                            pos: voxel.pos,
+                            block: voxel.block,
+                        },
+                        damage: damage as f32,
+                    }
+                }
+                (None, None) => continue,
+            };
+
+            events.send(hit);
+        }
+    }
+
+    world.damage_blocks(&mut commands, &chunks, block_damages);
+}
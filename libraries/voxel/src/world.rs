@@ -1,37 +1,84 @@
 use bevy::{
     prelude::*,
-    utils::HashMap,
+    utils::{HashMap, HashSet},
 };
 use blocks::Block;
 use chunk::{
-    Chunk, ChunkModification, ChunkNeighbors, ChunkUpdated, TerrainGenerated, VegetationGenerated,
-    CHUNK_SIZE,
+    Chunk, ChunkBorderUpdated, ChunkChanged, ChunkLightUpdated, ChunkModification, ChunkUpdated,
+    TerrainGenerated, VegetationGenerated, CHUNK_SIZE,
 };
+use coords::{BlockPos, ChunkPos};
+use fluid::{FluidSimulation, FLUID_TICK, SEA_LEVEL, SOURCE_LEVEL};
+use fragment::{FragmentState, VoxelFragment};
+use gravity::{FallingBlock, MIN_FALL_HEIGHT};
+use history::{EditHistory, EditTransaction, HistoryAction, HistoryEntry, VoxelEdit};
+use light::{LightChunks, LightEngine};
+use model::{SpawnVoxModel, VoxelModel};
+use schematic::{ClipboardAction, Schematic, SchematicClipboard};
+use vox::ExportVox;
 
 pub mod blocks;
 pub mod chunk;
+pub mod coords;
+pub mod fluid;
+pub mod fragment;
+pub mod gravity;
+pub mod history;
+pub mod light;
+pub mod model;
+pub mod raycast;
+pub mod schematic;
 pub mod tree;
+pub mod vox;
 
 pub struct VoxelWorldPlugin;
 
 impl Plugin for VoxelWorldPlugin {
     fn build(&self, app: &mut App) {
+        app.add_event::<ChunkChanged>();
+        app.add_event::<ExportVox>();
+        app.add_event::<SpawnVoxModel>();
+        app.add_event::<ClipboardAction>();
+        app.add_event::<HistoryAction>();
+        app.init_resource::<SchematicClipboard>();
         app.add_systems(
             Update,
             (
                 load_chunk,
                 update_chunk,
+                sync_aprons,
+                update_lighting,
                 generate_terrain,
                 generate_vegetation,
+                export_vox,
+                spawn_vox_models,
+                handle_clipboard,
+                handle_history,
+                activate_fluids,
+                detach_gravity_blocks,
+                detach_islands,
             ),
         );
+        app.add_systems(
+            FixedUpdate,
+            (simulate_fluids, update_falling_blocks, update_fragments),
+        );
     }
 }
 
 #[derive(Debug, Component)]
 pub struct VoxelWorld {
-    pub chunks: HashMap<IVec3, Entity>,
-    pub next_chunks: Vec<IVec3>,
+    pub chunks: HashMap<ChunkPos, Entity>,
+    pub next_chunks: Vec<ChunkPos>,
+
+    pub history: EditHistory,
+    pub fluids: FluidSimulation,
+}
+
+impl Default for VoxelWorld {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VoxelWorld {
@@ -39,186 +86,283 @@ impl VoxelWorld {
         Self {
             chunks: HashMap::new(),
             next_chunks: Vec::new(),
+            history: EditHistory::default(),
+            fluids: FluidSimulation::default(),
         }
     }
 
-    pub fn with_generation(mut self, chunks: Vec<IVec3>) -> Self {
+    pub fn with_generation(mut self, chunks: Vec<ChunkPos>) -> Self {
         self.generate(chunks);
 
         self
     }
 
-    pub fn generate(&mut self, chunks: Vec<IVec3>) {
+    pub fn generate(&mut self, chunks: Vec<ChunkPos>) {
         self.next_chunks.extend(chunks);
     }
 
-    pub fn neighbours(&self, pos: IVec3) -> ChunkNeighbors {
-        let IVec3 { x, y, z } = pos;
+    pub fn set_block(&self, commands: &mut Commands, pos: BlockPos, block: Block, health: u8) {
+        let (chunk_pos, local_pos) = pos.split();
 
-        ChunkNeighbors {
-            left: self.chunks.get(&IVec3::new(x - 1, y, z)).cloned(),
-            right: self.chunks.get(&IVec3::new(x + 1, y, z)).cloned(),
-            front: self.chunks.get(&IVec3::new(x, y, z + 1)).cloned(),
-            back: self.chunks.get(&IVec3::new(x, y, z - 1)).cloned(),
-            top: self.chunks.get(&IVec3::new(x, y + 1, z)).cloned(),
-            bottom: self.chunks.get(&IVec3::new(x, y - 1, z)).cloned(),
-        }
+        let mut modification = ChunkModification::new();
+        modification.blocks.push((local_pos, block, health));
+
+        self.push_modification(commands, chunk_pos, modification);
     }
 
-    pub fn set_block(
+    pub fn set_blocks(
         &self,
         commands: &mut Commands,
-        x: i32,
-        y: i32,
-        z: i32,
-        block: Block,
-        health: u8,
+        blocks: impl IntoIterator<Item = (BlockPos, Block, u8)>,
     ) {
-        let chunk_pos = IVec3::new(
-            match x >= 0 {
-                true => x / CHUNK_SIZE as i32,
-                false => (x - CHUNK_SIZE as i32 + 1) / CHUNK_SIZE as i32,
-            },
-            match y >= 0 {
-                true => y / CHUNK_SIZE as i32,
-                false => (y - CHUNK_SIZE as i32 + 1) / CHUNK_SIZE as i32,
-            },
-            match z >= 0 {
-                true => z / CHUNK_SIZE as i32,
-                false => (z - CHUNK_SIZE as i32 + 1) / CHUNK_SIZE as i32,
-            },
+        self.modify_blocks(
+            commands,
+            blocks
+                .into_iter()
+                .map(|(pos, block, health)| (pos, block, health, None)),
         );
+    }
 
+    pub fn set_history_blocks(
+        &self,
+        commands: &mut Commands,
+        blocks: impl IntoIterator<Item = (BlockPos, Block, u8, HistoryEntry)>,
+    ) {
+        self.modify_blocks(
+            commands,
+            blocks
+                .into_iter()
+                .map(|(pos, block, health, entry)| (pos, block, health, Some(entry))),
+        );
+    }
+
+    // Group the blocks by chunk so that only one command is issued per chunk
+    fn modify_blocks(
+        &self,
+        commands: &mut Commands,
+        blocks: impl IntoIterator<Item = (BlockPos, Block, u8, Option<HistoryEntry>)>,
+    ) {
+        let mut modifications = HashMap::<ChunkPos, ChunkModification>::new();
+
+        for (pos, block, health, entry) in blocks {
+            let (chunk_pos, local_pos) = pos.split();
+
+            let modification = modifications.entry(chunk_pos).or_default();
+
+            if let Some(entry) = entry {
+                modification
+                    .history
+                    .push((modification.blocks.len(), entry));
+            }
+
+            modification.blocks.push((local_pos, block, health));
+        }
+
+        for (chunk_pos, modification) in modifications {
+            self.push_modification(commands, chunk_pos, modification);
+        }
+    }
+
+    fn push_modification(
+        &self,
+        commands: &mut Commands,
+        chunk_pos: ChunkPos,
+        modification: ChunkModification,
+    ) {
         if let Some(entity) = self.chunks.get(&chunk_pos) {
             commands
                 .entity(*entity)
                 .add(move |mut entity: EntityWorldMut| {
-                    let data = (
-                        UVec3::new(
-                            x.rem_euclid(CHUNK_SIZE as i32) as u32,
-                            y.rem_euclid(CHUNK_SIZE as i32) as u32,
-                            z.rem_euclid(CHUNK_SIZE as i32) as u32,
-                        ),
-                        block,
-                        health,
-                    );
-
-                    if let Some(mut modification) = entity.get_mut::<ChunkModification>() {
-                        modification.blocks.push(data);
+                    if let Some(mut pending) = entity.get_mut::<ChunkModification>() {
+                        pending.append(modification);
                     } else {
-                        entity.insert(ChunkModification { blocks: vec![data] });
+                        entity.insert(modification);
                     }
                 });
         }
     }
 
-    pub fn update_neighbors(&self, commands: &mut Commands, pos: IVec3) {
-        let ChunkNeighbors {
-            left,
-            right,
-            front,
-            back,
-            top,
-            bottom,
-        } = self.neighbours(pos);
+    pub fn get_block(&self, chunks: &Query<&Chunk>, pos: BlockPos) -> Option<(Block, u8)> {
+        let (chunk_pos, local_pos) = pos.split();
 
-        if let Some(left) = left {
-            commands.entity(left).insert(ChunkUpdated);
-        }
+        let chunk = chunks.get(*self.chunks.get(&chunk_pos)?).ok()?;
 
-        if let Some(right) = right {
-            commands.entity(right).insert(ChunkUpdated);
-        }
+        let UVec3 { x, y, z } = local_pos.0;
+        let block = chunk.get_block(x as usize, y as usize, z as usize).ok()?;
+        let health = chunk.get_health(x as usize, y as usize, z as usize).ok()?;
+
+        Some((block, health))
+    }
+
+    // Remove health from solid blocks, the ones left without health are destroyed. The damage is committed to the
+    // history like any other edit so it can be undone.
+    pub fn damage_blocks(
+        &mut self,
+        commands: &mut Commands,
+        chunks: &Query<&Chunk>,
+        damages: impl IntoIterator<Item = (BlockPos, u8)>,
+    ) {
+        let mut total = HashMap::<BlockPos, u8>::new();
 
-        if let Some(front) = front {
-            commands.entity(front).insert(ChunkUpdated);
+        for (pos, damage) in damages {
+            let entry = total.entry(pos).or_default();
+            *entry = entry.saturating_add(damage);
         }
 
-        if let Some(back) = back {
-            commands.entity(back).insert(ChunkUpdated);
+        let blocks = total.into_iter().filter_map(|(pos, damage)| {
+            let (block, health) = self.get_block(chunks, pos)?;
+
+            if !block.is_solid() || damage == 0 {
+                return None;
+            }
+
+            match health.checked_sub(damage) {
+                Some(health) if health > 0 => Some((pos, block, health)),
+                _ => Some((pos, Block::Air, 15)),
+            }
+        });
+
+        let blocks = blocks.collect::<Vec<_>>();
+
+        let mut transaction = EditTransaction::new();
+        transaction.set_blocks(self, chunks, blocks);
+
+        self.commit(commands, transaction);
+    }
+
+    pub fn commit(&mut self, commands: &mut Commands, mut transaction: EditTransaction) {
+        if transaction.is_empty() {
+            return;
         }
 
-        if let Some(top) = top {
-            commands.entity(top).insert(ChunkUpdated);
+        transaction.id = self.history.next_id();
+
+        let entry = HistoryEntry::Commit(transaction.id);
+
+        self.set_history_blocks(
+            commands,
+            transaction
+                .edits
+                .iter()
+                .map(|edit| (edit.pos, edit.after.0, edit.after.1, entry)),
+        );
+
+        self.history.push(transaction);
+    }
+
+    pub fn undo(&mut self, commands: &mut Commands) -> bool {
+        match self.history.undo.pop() {
+            Some(transaction) => {
+                transaction.revert(self, commands);
+                self.history.redo.push(transaction);
+
+                true
+            }
+            None => false,
         }
+    }
 
-        if let Some(bottom) = bottom {
-            commands.entity(bottom).insert(ChunkUpdated);
+    pub fn redo(&mut self, commands: &mut Commands) -> bool {
+        match self.history.redo.pop() {
+            Some(transaction) => {
+                transaction.apply(self, commands);
+                self.history.undo.push(transaction);
+
+                true
+            }
+            None => false,
         }
     }
 }
 
-fn generate_terrain(
-    mut commands: Commands,
-    worlds: Query<&VoxelWorld>,
-    mut chunks: Query<(Entity, &mut Chunk), Without<TerrainGenerated>>,
-) {
-    for world in &worlds {
-        let mut count = 0;
-        for (entity, mut chunk) in &mut chunks {
-            if count >= 10 {
-                break;
-            }
-            count += 1;
+// Height of the surface generated at a column, before any edit
+pub fn terrain_height(x: i32, z: i32) -> i32 {
+    use perlin2d::PerlinNoise2D;
 
-            let IVec3 { x, y, z } = chunk.pos;
+    let terrain = PerlinNoise2D::new(6, 10.0, 0.5, 1.0, 2.0, (100.0, 100.0), 0.5, 101);
 
-            for xx in 0..CHUNK_SIZE {
-                for zz in 0..CHUNK_SIZE {
-                    let x = x * CHUNK_SIZE as i32 + xx as i32;
-                    let z = z * CHUNK_SIZE as i32 + zz as i32;
+    terrain.get_noise(x as f64, z as f64) as i32 + 20 + CHUNK_SIZE as i32
+}
 
-                    use perlin2d::PerlinNoise2D;
+// Chunks of the worlds waiting for their terrain or their trees, the chunks of the models get neither
+type UngeneratedChunks<'w, 's> =
+    Query<'w, 's, (Entity, &'static mut Chunk), (Without<TerrainGenerated>, Without<VoxelModel>)>;
 
-                    let terrain =
-                        PerlinNoise2D::new(6, 10.0, 0.5, 1.0, 2.0, (100.0, 100.0), 0.5, 101);
-                    let grass_transition =
-                        PerlinNoise2D::new(2, 20.0, 20.0, 5.0, 2.0, (100.0, 100.0), 0.5, 188);
+type TreelessChunks<'w, 's> =
+    Query<'w, 's, (Entity, &'static Chunk), (Without<VegetationGenerated>, Without<VoxelModel>)>;
 
-                    let height =
-                        terrain.get_noise(x as f64, z as f64) as i32 + 20 + CHUNK_SIZE as i32;
-                    let grass_level = grass_transition.get_noise(x as f64, z as f64) as i32 + 20;
+#[allow(clippy::explicit_counter_loop)]
+fn generate_terrain(mut commands: Commands, mut chunks: UngeneratedChunks) {
+    let mut count = 0;
+    for (entity, mut chunk) in &mut chunks {
+        if count >= 10 {
+            break;
+        }
+        count += 1;
 
-                    for yy in 0..CHUNK_SIZE {
-                        let y = y * CHUNK_SIZE as i32 + yy as i32;
+        let IVec3 { x, y, z } = chunk.pos.origin().0;
 
-                        if y > height {
-                            continue;
-                        }
+        for xx in 0..CHUNK_SIZE {
+            for zz in 0..CHUNK_SIZE {
+                let x = x + xx as i32;
+                let z = z + zz as i32;
+
+                use perlin2d::PerlinNoise2D;
+
+                let grass_transition =
+                    PerlinNoise2D::new(2, 20.0, 20.0, 5.0, 2.0, (100.0, 100.0), 0.5, 188);
 
-                        let block = if y as i32 >= height - 3 {
-                            if y as i32 >= grass_level {
-                                Block::LightGrass
-                            } else {
-                                Block::Grass
+                let height = terrain_height(x, z);
+                let grass_level = grass_transition.get_noise(x as f64, z as f64) as i32 + 20;
+
+                for yy in 0..CHUNK_SIZE {
+                    let y = y + yy as i32;
+
+                    if y > height {
+                        if y <= SEA_LEVEL {
+                            if let Err(error) =
+                                chunk.set_block(xx, yy, zz, Block::Water, SOURCE_LEVEL)
+                            {
+                                eprintln!("{}", error);
                             }
-                        } else if y as i32 > height - 15 {
-                            Block::Dirt
-                        } else {
-                            Block::Stone
-                        };
+                        }
 
-                        let random_health = (rand::random::<u8>() % 4) + 12;
+                        continue;
+                    }
 
-                        if let Err(error) = chunk.set_block(xx, yy, zz, block, random_health) {
-                            eprintln!("{}", error);
+                    let block = if y >= height - 3 {
+                        if height <= SEA_LEVEL + 2 {
+                            Block::Sand
+                        } else if y >= grass_level {
+                            Block::LightGrass
+                        } else {
+                            Block::Grass
                         }
+                    } else if y > height - 15 {
+                        Block::Dirt
+                    } else {
+                        Block::Stone
+                    };
+
+                    let random_health = (rand::random::<u8>() % 4) + 12;
+
+                    if let Err(error) = chunk.set_block(xx, yy, zz, block, random_health) {
+                        eprintln!("{}", error);
                     }
                 }
             }
-
-            commands.entity(entity).insert(TerrainGenerated);
-            commands.entity(entity).insert(ChunkUpdated);
-            world.update_neighbors(&mut commands, chunk.pos);
         }
+
+        chunk.revision += 1;
+
+        commands.entity(entity).insert(TerrainGenerated);
+        commands.entity(entity).insert(ChunkUpdated);
+        commands.entity(entity).insert(ChunkBorderUpdated);
     }
 }
 
-fn generate_vegetation(
-    mut commands: Commands,
-    worlds: Query<&VoxelWorld>,
-    chunks: Query<(Entity, &Chunk), Without<VegetationGenerated>>,
-) {
+#[allow(clippy::explicit_counter_loop)]
+fn generate_vegetation(mut commands: Commands, worlds: Query<&VoxelWorld>, chunks: TreelessChunks) {
     for world in &worlds {
         let mut count = 0;
         for (entity, chunk) in &chunks {
@@ -227,11 +371,7 @@ fn generate_vegetation(
             }
             count += 1;
 
-            let IVec3 { x, y, z } = chunk.pos;
-
-            use perlin2d::PerlinNoise2D;
-
-            let terrain = PerlinNoise2D::new(6, 10.0, 0.5, 1.0, 2.0, (100.0, 100.0), 0.5, 101);
+            let IVec3 { x, y, z } = chunk.pos.origin().0;
 
             let trees = (0..5)
                 .map(|_| {
@@ -243,20 +383,20 @@ fn generate_vegetation(
                 .collect::<Vec<_>>();
 
             for (tree_x, tree_z) in trees.clone() {
-                let x = x * CHUNK_SIZE as i32 + tree_x as i32;
-                let z = z * CHUNK_SIZE as i32 + tree_z as i32;
+                let x = x + tree_x as i32;
+                let z = z + tree_z as i32;
 
-                let height = terrain.get_noise(x as f64, z as f64) as i32 + 20 + CHUNK_SIZE as i32;
+                let height = terrain_height(x, z);
 
-                if height >= y * CHUNK_SIZE as i32 + CHUNK_SIZE as i32 {
+                if height >= y + CHUNK_SIZE as i32 {
                     continue;
                 }
 
-                if height < y * CHUNK_SIZE as i32 {
+                if height < y || height < SEA_LEVEL {
                     continue;
                 }
 
-                tree::generate_tree(&mut commands, world, x, height, z);
+                tree::generate_tree(&mut commands, world, BlockPos::new(x, height, z));
             }
 
             commands.entity(entity).insert(VegetationGenerated);
@@ -267,13 +407,12 @@ fn generate_vegetation(
 fn load_chunk(mut commands: Commands, mut worlds: Query<(Entity, &mut VoxelWorld)>) {
     for (entity, mut world) in &mut worlds {
         while let Some(next) = world.next_chunks.pop() {
-            let IVec3 { x, y, z } = next;
-
-            if world.chunks.contains_key(&IVec3::new(x, y, z)) {
+            if world.chunks.contains_key(&next) {
                 continue;
             }
 
-            let chunk = chunk::Chunk::new(IVec3::new(x, y, z));
+            let chunk = chunk::Chunk::new(next);
+            let IVec3 { x, y, z } = next.0;
 
             commands.entity(entity).with_children(|parent| {
                 let id = parent
@@ -281,40 +420,524 @@ fn load_chunk(mut commands: Commands, mut worlds: Query<(Entity, &mut VoxelWorld
                     .insert(Name::new(format!("Chunk ({}, {}, {})", x, y, z)))
                     .id();
 
-                world.chunks.insert(IVec3::new(x, y, z), id);
+                world.chunks.insert(next, id);
             });
         }
     }
 }
 
+// Chunks with blocks waiting to be set, the chunks of the worlds have their world as parent
+type ModifiedChunks<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut Chunk,
+        &'static ChunkModification,
+        Option<&'static Parent>,
+    ),
+    Without<ChunkUpdated>,
+>;
+
 fn update_chunk(
     mut commands: Commands,
+    mut events: EventWriter<ChunkChanged>,
     mut worlds: Query<&mut VoxelWorld>,
-    mut chunks: Query<(Entity, &mut Chunk, &ChunkModification), Without<ChunkUpdated>>,
+    mut chunks: ModifiedChunks,
 ) {
-    for world in &mut worlds {
-        for (chunk_id, mut chunk, modification) in &mut chunks {
-            if modification.blocks.is_empty() {
+    for (chunk_id, mut chunk, modification, parent) in &mut chunks {
+        if modification.blocks.is_empty() {
+            continue;
+        }
+
+        let entries = modification
+            .history
+            .iter()
+            .copied()
+            .collect::<HashMap<_, _>>();
+
+        let mut voxels = Vec::new();
+        let mut replaced = Vec::new();
+
+        for (index, (pos, block, health)) in modification.blocks.iter().enumerate() {
+            let (x, y, z) = (pos.x as usize, pos.y as usize, pos.z as usize);
+
+            // Undo and redo leave the voxels changed since by other edits alone
+            if let Some(HistoryEntry::Restore(expected)) = entries.get(&index) {
+                let current = chunk
+                    .get_block(x, y, z)
+                    .and_then(|block| Ok((block, chunk.get_health(x, y, z)?)));
+
+                if !current.is_ok_and(|current| current == *expected) {
+                    continue;
+                }
+            }
+
+            match chunk.set_block(x, y, z, *block, *health) {
+                Ok(before) => {
+                    if let Some(HistoryEntry::Commit(id)) = entries.get(&index) {
+                        replaced.push((*id, chunk.pos.block(*pos), before));
+                    }
+
+                    if before != (*block, *health) {
+                        voxels.push(VoxelEdit {
+                            pos: chunk.pos.block(*pos),
+                            before,
+                            after: (*block, *health),
+                        });
+                    }
+                }
+                Err(error) => eprintln!("{}", error),
+            }
+        }
+
+        if let Some(mut world) = parent.and_then(|parent| worlds.get_mut(parent.get()).ok()) {
+            for (id, pos, before) in replaced {
+                world.history.record(id, pos, before);
+            }
+        }
+
+        commands.entity(chunk_id).remove::<ChunkModification>();
+
+        if voxels.is_empty() {
+            continue;
+        }
+
+        chunk.revision += 1;
+
+        if modification
+            .blocks
+            .iter()
+            .any(|(pos, _, _)| Chunk::is_on_border(*pos))
+        {
+            commands.entity(chunk_id).insert(ChunkBorderUpdated);
+        }
+
+        events.send(ChunkChanged {
+            chunk: chunk_id,
+            pos: chunk.pos,
+            revision: chunk.revision,
+            voxels,
+        });
+
+        commands.entity(chunk_id).insert(ChunkUpdated);
+    }
+}
+
+// Copy the border of the updated chunks into the aprons of their neighbours and the other way around
+fn sync_aprons(
+    mut commands: Commands,
+    worlds: Query<&VoxelWorld>,
+    updated: Query<(Entity, &Parent), With<ChunkBorderUpdated>>,
+    generated: Query<(), With<TerrainGenerated>>,
+    mut chunks: Query<&mut Chunk>,
+) {
+    for (chunk_id, parent) in &updated {
+        commands.entity(chunk_id).remove::<ChunkBorderUpdated>();
+
+        let Ok(world) = worlds.get(parent.get()) else {
+            continue;
+        };
+
+        let Ok(pos) = chunks.get(chunk_id).map(|chunk| chunk.pos) else {
+            continue;
+        };
+
+        for offset in ChunkPos::aabb(ChunkPos::new(-1, -1, -1), ChunkPos::new(1, 1, 1)) {
+            let Some(neighbour_id) = world.chunks.get(&pos.offset(offset.0)).cloned() else {
+                continue;
+            };
+
+            if neighbour_id == chunk_id || !generated.contains(neighbour_id) {
                 continue;
             }
 
-            for (pos, block, health) in modification.blocks.iter() {
-                if let Err(error) = chunk.set_block(
-                    pos.x as usize,
-                    pos.y as usize,
-                    pos.z as usize,
-                    *block,
-                    *health,
-                ) {
-                    eprintln!("{}", error);
+            let Ok([mut chunk, mut neighbour]) = chunks.get_many_mut([chunk_id, neighbour_id])
+            else {
+                continue;
+            };
+
+            if neighbour.sync_apron(&chunk) {
+                commands.entity(neighbour_id).insert(ChunkUpdated);
+            }
+
+            if chunk.sync_apron(&neighbour) {
+                commands.entity(chunk_id).insert(ChunkUpdated);
+            }
+        }
+    }
+}
+
+fn update_lighting(
+    mut commands: Commands,
+    mut events: EventReader<ChunkChanged>,
+    worlds: Query<(Entity, &VoxelWorld)>,
+    generated: Query<(Entity, &Parent), Added<TerrainGenerated>>,
+    parents: Query<&Parent, With<Chunk>>,
+    mut chunks: LightChunks,
+) {
+    let changes = events.read().collect::<Vec<_>>();
+
+    for (world_id, world) in &worlds {
+        let mut generated = generated
+            .iter()
+            .filter(|(_, parent)| parent.get() == world_id)
+            .filter_map(|(chunk_id, _)| chunks.get(chunk_id).ok().map(|(chunk, _)| chunk.pos))
+            .collect::<Vec<_>>();
+
+        // From the top so the sky light goes down through the columns of new chunks at once
+        generated.sort_by_key(|pos| -pos.y);
+
+        let mut engine = LightEngine::new(world, &mut chunks);
+
+        for pos in generated {
+            engine.relight_chunk(pos);
+        }
+
+        for change in &changes {
+            if parents
+                .get(change.chunk)
+                .is_ok_and(|parent| parent.get() == world_id)
+            {
+                for voxel in &change.voxels {
+                    engine.update_block(voxel.pos);
+                }
+            }
+        }
+
+        engine.propagate();
+
+        for chunk_id in engine.updated {
+            commands.entity(chunk_id).insert(ChunkLightUpdated);
+        }
+    }
+}
+
+// Voxels next to an edit may have to flow, for example when a crater is dug next to some water
+fn activate_fluids(
+    mut events: EventReader<ChunkChanged>,
+    mut worlds: Query<&mut VoxelWorld>,
+    parents: Query<&Parent, With<Chunk>>,
+) {
+    for change in events.read() {
+        let Ok(mut world) = parents
+            .get(change.chunk)
+            .and_then(|parent| worlds.get_mut(parent.get()))
+        else {
+            continue;
+        };
+
+        for voxel in &change.voxels {
+            if voxel.before.0.is_fluid() || voxel.after.0.is_fluid() || voxel.after.0 == Block::Air
+            {
+                world.fluids.activate(voxel.pos);
+            }
+        }
+    }
+}
+
+fn simulate_fluids(
+    mut commands: Commands,
+    mut worlds: Query<&mut VoxelWorld>,
+    chunks: Query<&Chunk>,
+) {
+    for mut world in &mut worlds {
+        world.fluids.ticks += 1;
+
+        if world.fluids.ticks % FLUID_TICK != 0 || world.fluids.active.is_empty() {
+            continue;
+        }
+
+        let mut fluids = std::mem::take(&mut world.fluids);
+        let changes = fluids.step(&world, &chunks);
+        world.fluids = fluids;
+
+        world.set_blocks(&mut commands, changes);
+    }
+}
+
+// Gravity blocks above an edit may have lost their support, they are replaced by falling blocks
+fn detach_gravity_blocks(
+    mut commands: Commands,
+    mut events: EventReader<ChunkChanged>,
+    worlds: Query<(Entity, &VoxelWorld)>,
+    parents: Query<&Parent, With<Chunk>>,
+    chunks: Query<&Chunk>,
+) {
+    let mut candidates = HashMap::<Entity, HashSet<BlockPos>>::new();
+
+    for change in events.read() {
+        if let Ok(parent) = parents.get(change.chunk) {
+            candidates.entry(parent.get()).or_default().extend(
+                change
+                    .voxels
+                    .iter()
+                    .flat_map(|voxel| [voxel.pos, voxel.pos.offset(IVec3::Y)]),
+            );
+        }
+    }
+
+    for (world_id, world) in &worlds {
+        let Some(candidates) = candidates.remove(&world_id) else {
+            continue;
+        };
+
+        let blocks = gravity::unsupported_blocks(world, &chunks, candidates);
+
+        world.set_blocks(
+            &mut commands,
+            blocks.iter().map(|(pos, _, _)| (*pos, Block::Air, 15)),
+        );
+
+        for (pos, block, health) in blocks {
+            commands
+                .spawn(FallingBlock {
+                    world: world_id,
+                    block,
+                    health,
+                    velocity: 0.0,
+                })
+                .insert(SpatialBundle::from_transform(Transform::from_translation(
+                    pos.center(),
+                )))
+                .insert(Name::new("Falling block"));
+        }
+    }
+}
+
+fn update_falling_blocks(
+    mut commands: Commands,
+    time: Res<Time>,
+    worlds: Query<&VoxelWorld>,
+    chunks: Query<&Chunk>,
+    mut blocks: Query<(Entity, &mut FallingBlock, &mut Transform)>,
+) {
+    let mut landed = HashSet::new();
+
+    for (entity, mut falling, mut transform) in &mut blocks {
+        let Ok(world) = worlds.get(falling.world) else {
+            commands.entity(entity).despawn();
+
+            continue;
+        };
+
+        if let Some(pos) = falling.fall(&mut transform, world, &chunks, time.delta_seconds()) {
+            // Blocks landing on an occupied voxel stack on top of it instead of replacing it
+            if let Some(pos) = gravity::landing_spot(world, &chunks, &landed, pos) {
+                world.set_block(&mut commands, pos, falling.block, falling.health);
+                landed.insert(pos);
+            }
+
+            commands.entity(entity).despawn();
+        } else if transform.translation.y < MIN_FALL_HEIGHT {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// Structures cut from the ground by an edit become fragments, for example a tower whose base was shot out
+fn detach_islands(
+    mut commands: Commands,
+    mut events: EventReader<ChunkChanged>,
+    worlds: Query<(Entity, &VoxelWorld)>,
+    parents: Query<&Parent, With<Chunk>>,
+    chunks: Query<&Chunk>,
+    generated: Query<(), With<TerrainGenerated>>,
+) {
+    let mut candidates = HashMap::<Entity, Vec<BlockPos>>::new();
+
+    for change in events.read() {
+        if let Ok(parent) = parents.get(change.chunk) {
+            candidates.entry(parent.get()).or_default().extend(
+                change
+                    .voxels
+                    .iter()
+                    .filter(|voxel| {
+                        voxel.before.0.is_solid()
+                            && !voxel.before.0.has_gravity()
+                            && !voxel.after.0.is_solid()
+                    })
+                    .flat_map(|voxel| voxel.pos.neighbours()),
+            );
+        }
+    }
+
+    for (world_id, world) in &worlds {
+        let Some(candidates) = candidates.remove(&world_id) else {
+            continue;
+        };
+
+        let mut visited = HashSet::new();
+
+        for start in candidates {
+            let Some(island) =
+                fragment::find_island(world, &chunks, &generated, start, &mut visited)
+            else {
+                continue;
+            };
+
+            let (fragment, chunk, transform) =
+                VoxelFragment::from_island(world_id, world, &chunks, &island);
+
+            world.set_blocks(
+                &mut commands,
+                island.iter().map(|pos| (*pos, Block::Air, 15)),
+            );
+
+            commands
+                .spawn((fragment, VoxelModel, chunk))
+                .insert(SpatialBundle::from_transform(transform))
+                .insert(Name::new("Voxel fragment"));
+        }
+    }
+}
+
+fn update_fragments(
+    mut commands: Commands,
+    time: Res<Time>,
+    worlds: Query<&VoxelWorld>,
+    chunks: Query<&Chunk>,
+    mut fragments: Query<(Entity, &mut VoxelFragment, &mut Transform, &Chunk)>,
+) {
+    for (entity, mut fragment, mut transform, chunk) in &mut fragments {
+        let Ok(world) = worlds.get(fragment.world) else {
+            commands.entity(entity).despawn_recursive();
+
+            continue;
+        };
+
+        match fragment.step(&mut transform, world, &chunks, time.delta_seconds()) {
+            FragmentState::Moving if transform.translation.y < MIN_FALL_HEIGHT => {
+                commands.entity(entity).despawn_recursive();
+            }
+            FragmentState::Moving => {}
+            FragmentState::Merge => {
+                world.set_blocks(
+                    &mut commands,
+                    fragment.merged_blocks(&transform, chunk, world, &chunks),
+                );
+
+                commands.entity(entity).despawn_recursive();
+            }
+            FragmentState::Shatter => {
+                for (debris, pos) in fragment.debris(&transform) {
+                    commands
+                        .spawn(debris)
+                        .insert(SpatialBundle::from_transform(Transform::from_translation(
+                            pos,
+                        )))
+                        .insert(Name::new("Falling block"));
                 }
+
+                commands.entity(entity).despawn_recursive();
             }
+        }
+    }
+}
 
-            commands.entity(chunk_id).remove::<ChunkModification>();
+fn export_vox(
+    mut events: EventReader<ExportVox>,
+    worlds: Query<&VoxelWorld>,
+    chunks: Query<&Chunk>,
+) {
+    for ExportVox { min, max, path } in events.read() {
+        for world in &worlds {
+            if let Err(error) = vox::export_vox(world, &chunks, *min, *max, path) {
+                eprintln!("{}", error);
+            }
+        }
+    }
+}
+
+fn spawn_vox_models(mut commands: Commands, mut events: EventReader<SpawnVoxModel>) {
+    for SpawnVoxModel { path, transform } in events.read() {
+        match VoxelModel::load_vox(path) {
+            Ok(chunk) => {
+                commands
+                    .spawn((VoxelModel, chunk))
+                    .insert(SpatialBundle::from_transform(*transform))
+                    .insert(Name::new(format!("Voxel model ({})", path)));
+            }
+            Err(error) => eprintln!("{}", error),
+        }
+    }
+}
 
-            commands.entity(chunk_id).insert(ChunkUpdated);
+fn handle_clipboard(
+    mut commands: Commands,
+    mut events: EventReader<ClipboardAction>,
+    mut clipboard: ResMut<SchematicClipboard>,
+    mut worlds: Query<&mut VoxelWorld>,
+    chunks: Query<&Chunk>,
+) {
+    for action in events.read() {
+        match action {
+            ClipboardAction::Copy { min, max } => {
+                for world in &worlds {
+                    match Schematic::copy(world, &chunks, *min, *max) {
+                        Ok(schematic) => clipboard.schematic = Some(schematic),
+                        Err(error) => eprintln!("{}", error),
+                    }
+                }
+            }
+            ClipboardAction::Paste {
+                origin,
+                replace_air,
+            } => {
+                if let Some(schematic) = &clipboard.schematic {
+                    for mut world in &mut worlds {
+                        let mut transaction = EditTransaction::new();
+                        transaction.set_blocks(
+                            &world,
+                            &chunks,
+                            schematic.blocks_at(*origin, *replace_air),
+                        );
+
+                        world.commit(&mut commands, transaction);
+                    }
+                }
+            }
+            ClipboardAction::Rotate {
+                axis,
+                quarter_turns,
+            } => {
+                clipboard.schematic = clipboard
+                    .schematic
+                    .as_ref()
+                    .map(|schematic| schematic.rotate(*axis, *quarter_turns));
+            }
+            ClipboardAction::Mirror { axis } => {
+                clipboard.schematic = clipboard
+                    .schematic
+                    .as_ref()
+                    .map(|schematic| schematic.mirror(*axis));
+            }
+            ClipboardAction::Save { path } => {
+                if let Some(schematic) = &clipboard.schematic {
+                    if let Err(error) = schematic.save(path) {
+                        eprintln!("{}", error);
+                    }
+                }
+            }
+            ClipboardAction::Load { path } => match Schematic::load(path) {
+                Ok(schematic) => clipboard.schematic = Some(schematic),
+                Err(error) => eprintln!("{}", error),
+            },
+        }
+    }
+}
 
-            world.update_neighbors(&mut commands, chunk.pos);
+fn handle_history(
+    mut commands: Commands,
+    mut events: EventReader<HistoryAction>,
+    mut worlds: Query<&mut VoxelWorld>,
+) {
+    for action in events.read() {
+        for mut world in &mut worlds {
+            match action {
+                HistoryAction::Undo => world.undo(&mut commands),
+                HistoryAction::Redo => world.redo(&mut commands),
+            };
         }
     }
 }
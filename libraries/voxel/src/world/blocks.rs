@@ -1,4 +1,7 @@
-#[derive(Debug, Clone, Copy, PartialEq)]
+use bevy::color::LinearRgba;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Block {
     Air = 0,
     Grass = 1,
@@ -8,6 +11,12 @@ pub enum Block {
     Wood = 5,
     Leaves = 6,
     LightLeaves = 7,
+    Lamp = 8,
+    Glass = 9,
+    Water = 10,
+    Sand = 11,
+    Gravel = 12,
+    Debris = 13,
 }
 
 impl Block {
@@ -25,7 +34,68 @@ impl Block {
             5 => Self::Wood,
             6 => Self::Leaves,
             7 => Self::LightLeaves,
+            8 => Self::Lamp,
+            9 => Self::Glass,
+            10 => Self::Water,
+            11 => Self::Sand,
+            12 => Self::Gravel,
+            13 => Self::Debris,
             _ => Self::Air,
         }
     }
+
+    // Same palette as the one used in the chunk shader
+    pub fn color(&self) -> LinearRgba {
+        match self {
+            Self::Air => LinearRgba::rgb(0.0, 0.0, 0.0),
+            Self::Grass => LinearRgba::rgb(0.07, 0.5, 0.07),
+            Self::Dirt => LinearRgba::rgb(0.5, 0.25, 0.0),
+            Self::Stone => LinearRgba::rgb(0.7, 0.7, 0.7),
+            Self::LightGrass => LinearRgba::rgb(0.07, 0.6, 0.07),
+            Self::Wood => LinearRgba::rgb(0.35, 0.20, 0.0),
+            Self::Leaves => LinearRgba::rgb(0.07, 0.3, 0.07),
+            Self::LightLeaves => LinearRgba::rgb(0.15, 0.6, 0.2),
+            Self::Lamp => LinearRgba::rgb(1.0, 0.85, 0.4),
+            Self::Glass => LinearRgba::rgb(0.8, 0.9, 0.95),
+            Self::Water => LinearRgba::rgb(0.1, 0.35, 0.8),
+            Self::Sand => LinearRgba::rgb(0.86, 0.8, 0.55),
+            Self::Gravel => LinearRgba::rgb(0.5, 0.48, 0.45),
+            Self::Debris => LinearRgba::rgb(0.4, 0.36, 0.3),
+        }
+    }
+
+    // Whether the block hides its neighbours and stops sky and block light
+    pub fn is_opaque(&self) -> bool {
+        *self != Self::Air && !self.is_transparent()
+    }
+
+    // Blocks that hold the blocks around them, fluids are not part of structures
+    pub fn is_solid(&self) -> bool {
+        *self != Self::Air && !self.is_fluid()
+    }
+
+    // Gravity blocks fall when the block below them is empty
+    pub fn has_gravity(&self) -> bool {
+        matches!(self, Self::Sand | Self::Gravel | Self::Debris)
+    }
+
+    // Fluids flow with the fluid simulation and store their level instead of their health
+    pub fn is_fluid(&self) -> bool {
+        *self == Self::Water
+    }
+
+    // Transparent blocks let the light through and are rendered with blending
+    pub fn is_transparent(&self) -> bool {
+        matches!(
+            self,
+            Self::Leaves | Self::LightLeaves | Self::Glass | Self::Water
+        )
+    }
+
+    pub fn light_emission(&self) -> u8 {
+        match self {
+            Self::Lamp => 14,
+            _ => 0,
+        }
+    }
 }
@@ -1,52 +1,133 @@
 use bevy::prelude::*;
 
-use super::blocks::Block;
+use super::{
+    blocks::Block,
+    coords::{ChunkPos, LocalPos},
+    history::{HistoryEntry, VoxelEdit},
+};
 
+// The occupancy masks are u64 with one bit of padding on each side, so a chunk can't be larger than 62
+#[cfg(not(feature = "large-chunks"))]
 pub const CHUNK_SIZE: usize = 31;
+#[cfg(feature = "large-chunks")]
+pub const CHUNK_SIZE: usize = 62;
+
+// Bits of a mask that belong to the chunk itself, bit 0 and bit CHUNK_SIZE + 1 are the padded border
+pub const INNER_MASK: u64 = ((1 << CHUNK_SIZE) - 1) << 1;
+
+// Size of the chunk with its one voxel apron on each side
+pub const PADDED_SIZE: usize = CHUNK_SIZE + 2;
 
 #[derive(Debug, Component)]
 pub struct Chunk {
-    pub pos: IVec3,
+    pub pos: ChunkPos,
+
+    // Incremented each time the content of the chunk changes
+    pub revision: u64,
 
     // Store the blocks in a flat array : 4bits for the block type and 4bits for the block health
     pub blocks: [u8; CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE],
 
-    // Mask to determine if a block is solid for fast face culling
-    pub x_axis: [u32; CHUNK_SIZE * CHUNK_SIZE],
-    pub y_axis: [u32; CHUNK_SIZE * CHUNK_SIZE],
-    pub z_axis: [u32; CHUNK_SIZE * CHUNK_SIZE],
+    // Masks for fast face culling, opaque blocks hide the faces behind them while transparent ones are meshed apart
+    pub opaque: Occupancy,
+    pub transparent: Occupancy,
+
+    // Sky light in the 4 high bits and block light in the 4 low bits, with the same one voxel apron as the masks
+    pub light: [u8; PADDED_SIZE * PADDED_SIZE * PADDED_SIZE],
 }
 
-pub struct ChunkMask {
-    pub left: [u32; CHUNK_SIZE * CHUNK_SIZE],
-    pub right: [u32; CHUNK_SIZE * CHUNK_SIZE],
-    pub bottom: [u32; CHUNK_SIZE * CHUNK_SIZE],
-    pub top: [u32; CHUNK_SIZE * CHUNK_SIZE],
-    pub back: [u32; CHUNK_SIZE * CHUNK_SIZE],
-    pub front: [u32; CHUNK_SIZE * CHUNK_SIZE],
+// Occupancy of the chunk with a one voxel apron copied from the neighbours,
+// so the block at (x, y, z) is the bit x + 1 of x_axis[(y + 1) + (z + 1) * PADDED_SIZE]
+#[derive(Debug, Clone)]
+pub struct Occupancy {
+    pub x_axis: [u64; PADDED_SIZE * PADDED_SIZE],
+    pub y_axis: [u64; PADDED_SIZE * PADDED_SIZE],
+    pub z_axis: [u64; PADDED_SIZE * PADDED_SIZE],
 }
 
-impl ChunkMask {
+impl Default for Occupancy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Occupancy {
     pub fn new() -> Self {
         Self {
-            left: [0; CHUNK_SIZE * CHUNK_SIZE],
-            right: [0; CHUNK_SIZE * CHUNK_SIZE],
-            bottom: [0; CHUNK_SIZE * CHUNK_SIZE],
-            top: [0; CHUNK_SIZE * CHUNK_SIZE],
-            back: [0; CHUNK_SIZE * CHUNK_SIZE],
-            front: [0; CHUNK_SIZE * CHUNK_SIZE],
+            x_axis: [0b0; PADDED_SIZE * PADDED_SIZE],
+            y_axis: [0b0; PADDED_SIZE * PADDED_SIZE],
+            z_axis: [0b0; PADDED_SIZE * PADDED_SIZE],
+        }
+    }
+
+    // Occupancy at a padded position, each component is in -1..=CHUNK_SIZE
+    pub fn get(&self, pos: IVec3) -> bool {
+        let IVec3 { x, y, z } = pos + IVec3::ONE;
+
+        if x < 0 || y < 0 || z < 0 {
+            return false;
+        }
+
+        let (x, y, z) = (x as usize, y as usize, z as usize);
+
+        if x >= PADDED_SIZE || y >= PADDED_SIZE || z >= PADDED_SIZE {
+            return false;
+        }
+
+        self.x_axis[y + z * PADDED_SIZE] & (1 << x) != 0
+    }
+
+    pub fn set(&mut self, pos: IVec3, occupied: bool) {
+        let IVec3 { x, y, z } = pos + IVec3::ONE;
+        let (x, y, z) = (x as usize, y as usize, z as usize);
+
+        match occupied {
+            true => {
+                self.x_axis[y + z * PADDED_SIZE] |= 1 << x;
+                self.y_axis[x + z * PADDED_SIZE] |= 1 << y;
+                self.z_axis[x + y * PADDED_SIZE] |= 1 << z;
+            }
+            false => {
+                self.x_axis[y + z * PADDED_SIZE] &= !(1 << x);
+                self.y_axis[x + z * PADDED_SIZE] &= !(1 << y);
+                self.z_axis[x + y * PADDED_SIZE] &= !(1 << z);
+            }
         }
     }
 }
 
 #[derive(Debug, Component)]
 pub struct ChunkModification {
-    pub blocks: Vec<(UVec3, Block, u8)>,
+    pub blocks: Vec<(LocalPos, Block, u8)>,
+    // Blocks set by the history of the world, by index in the blocks
+    pub history: Vec<(usize, HistoryEntry)>,
 }
 
 impl ChunkModification {
     pub fn new() -> Self {
-        Self { blocks: Vec::new() }
+        Self {
+            blocks: Vec::new(),
+            history: Vec::new(),
+        }
+    }
+
+    // The blocks of the other modification are applied after the current ones
+    pub fn append(&mut self, other: ChunkModification) {
+        let offset = self.blocks.len();
+
+        self.history.extend(
+            other
+                .history
+                .into_iter()
+                .map(|(index, entry)| (index + offset, entry)),
+        );
+        self.blocks.extend(other.blocks);
+    }
+}
+
+impl Default for ChunkModification {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -56,26 +137,33 @@ pub struct TerrainGenerated;
 #[derive(Debug, Component)]
 pub struct VegetationGenerated;
 
-pub struct ChunkNeighbors {
-    pub left: Option<Entity>,
-    pub right: Option<Entity>,
-    pub bottom: Option<Entity>,
-    pub top: Option<Entity>,
-    pub back: Option<Entity>,
-    pub front: Option<Entity>,
-}
-
 #[derive(Debug, Component)]
 pub struct ChunkUpdated; // "Event" to notify that the chunk has been updated
 
+#[derive(Debug, Component)]
+pub struct ChunkBorderUpdated; // "Event" to notify that the neighbours aprons must be synced
+
+#[derive(Debug, Component)]
+pub struct ChunkLightUpdated; // "Event" to notify that the light of the chunk or of its apron changed
+
+// Every voxel that changed in a chunk during a tick, with its previous and new content
+#[derive(Debug, Clone, Event)]
+pub struct ChunkChanged {
+    pub chunk: Entity,
+    pub pos: ChunkPos,
+    pub revision: u64,
+    pub voxels: Vec<VoxelEdit>,
+}
+
 impl Chunk {
-    pub fn new(pos: IVec3) -> Self {
+    pub fn new(pos: ChunkPos) -> Self {
         Self {
             pos,
+            revision: 0,
             blocks: [15 << 4 | Block::Air.as_u8(); CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE],
-            x_axis: [0b0; CHUNK_SIZE * CHUNK_SIZE],
-            y_axis: [0b0; CHUNK_SIZE * CHUNK_SIZE],
-            z_axis: [0b0; CHUNK_SIZE * CHUNK_SIZE],
+            opaque: Occupancy::new(),
+            transparent: Occupancy::new(),
+            light: [0; PADDED_SIZE * PADDED_SIZE * PADDED_SIZE],
         }
     }
 
@@ -83,6 +171,10 @@ impl Chunk {
         self.blocks.into()
     }
 
+    pub fn light(&self) -> Vec<u8> {
+        self.light.into()
+    }
+
     pub fn get_block(&self, x: usize, y: usize, z: usize) -> eyre::Result<Block> {
         if x >= CHUNK_SIZE || y >= CHUNK_SIZE || z >= CHUNK_SIZE {
             return Err(eyre::eyre!(format!("Index {:?} out of bounds", (x, y, z))));
@@ -108,18 +200,92 @@ impl Chunk {
         z: usize,
         block: Block,
         health: u8,
-    ) -> eyre::Result<()> {
+    ) -> eyre::Result<(Block, u8)> {
         if x >= CHUNK_SIZE || y >= CHUNK_SIZE || z >= CHUNK_SIZE {
             return Err(eyre::eyre!(format!("Index {:?} out of bounds", (x, y, z))));
         }
 
+        let previous = (self.get_block(x, y, z)?, self.get_health(x, y, z)?);
+
         self.blocks[x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE] = block.as_u8();
         self.blocks[x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE] |= health << 4;
 
-        self.x_axis[y + z * CHUNK_SIZE] |= 1 << x;
-        self.y_axis[x + z * CHUNK_SIZE] |= 1 << y;
-        self.z_axis[x + y * CHUNK_SIZE] |= 1 << z;
+        let pos = IVec3::new(x as i32, y as i32, z as i32);
+
+        self.opaque.set(pos, block.is_opaque());
+        self.transparent.set(pos, block.is_transparent());
+
+        Ok(previous)
+    }
+
+    // Whether the block at a padded position hides the faces of its neighbours
+    pub fn is_opaque(&self, pos: IVec3) -> bool {
+        self.opaque.get(pos)
+    }
+
+    pub fn is_transparent(&self, pos: IVec3) -> bool {
+        self.transparent.get(pos)
+    }
+
+    // Light at a padded position, each component is in -1..=CHUNK_SIZE
+    pub fn get_light(&self, pos: IVec3) -> u8 {
+        let IVec3 { x, y, z } = pos + IVec3::ONE;
+
+        self.light[x as usize + y as usize * PADDED_SIZE + z as usize * PADDED_SIZE * PADDED_SIZE]
+    }
+
+    pub fn set_light(&mut self, pos: IVec3, light: u8) {
+        let IVec3 { x, y, z } = pos + IVec3::ONE;
+
+        self.light
+            [x as usize + y as usize * PADDED_SIZE + z as usize * PADDED_SIZE * PADDED_SIZE] =
+            light;
+    }
+
+    pub fn is_on_border(pos: LocalPos) -> bool {
+        pos.x == 0
+            || pos.y == 0
+            || pos.z == 0
+            || pos.x as usize == CHUNK_SIZE - 1
+            || pos.y as usize == CHUNK_SIZE - 1
+            || pos.z as usize == CHUNK_SIZE - 1
+    }
+
+    // Copy the border of a neighbour (one of the 26 around this chunk) into the apron, returns true if it changed
+    pub fn sync_apron(&mut self, neighbour: &Chunk) -> bool {
+        let offset = neighbour.pos.0 - self.pos.0;
+
+        if offset == IVec3::ZERO || offset.abs().max_element() > 1 {
+            return false;
+        }
+
+        let range = |offset: i32| match offset {
+            -1 => -1..0,
+            0 => 0..CHUNK_SIZE as i32,
+            _ => CHUNK_SIZE as i32..CHUNK_SIZE as i32 + 1,
+        };
+
+        let mut changed = false;
+
+        for z in range(offset.z) {
+            for y in range(offset.y) {
+                for x in range(offset.x) {
+                    let pos = IVec3::new(x, y, z);
+                    let source = pos - offset * CHUNK_SIZE as i32;
+
+                    let opaque = neighbour.is_opaque(source);
+                    let transparent = neighbour.is_transparent(source);
+
+                    if self.is_opaque(pos) != opaque || self.is_transparent(pos) != transparent {
+                        self.opaque.set(pos, opaque);
+                        self.transparent.set(pos, transparent);
+
+                        changed = true;
+                    }
+                }
+            }
+        }
 
-        Ok(())
+        changed
     }
 }
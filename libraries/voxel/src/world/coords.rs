@@ -0,0 +1,227 @@
+use std::ops::Deref;
+
+use bevy::prelude::*;
+
+use super::chunk::CHUNK_SIZE;
+
+// Position of a block in world space
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockPos(pub IVec3);
+
+// Position of a chunk in the chunk grid
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkPos(pub IVec3);
+
+// Position of a block inside its chunk, each component is in 0..CHUNK_SIZE
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LocalPos(pub UVec3);
+
+pub const NEIGHBOURS: [IVec3; 6] = [
+    IVec3::NEG_X,
+    IVec3::X,
+    IVec3::NEG_Y,
+    IVec3::Y,
+    IVec3::NEG_Z,
+    IVec3::Z,
+];
+
+// Inclusive iteration over every position between min and max
+fn iter_aabb(min: IVec3, max: IVec3) -> impl Iterator<Item = IVec3> {
+    let (min, max) = (min.min(max), min.max(max));
+
+    (min.z..=max.z).flat_map(move |z| {
+        (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| IVec3::new(x, y, z)))
+    })
+}
+
+impl BlockPos {
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Self(IVec3::new(x, y, z))
+    }
+
+    pub fn chunk(&self) -> ChunkPos {
+        ChunkPos(self.0.div_euclid(IVec3::splat(CHUNK_SIZE as i32)))
+    }
+
+    pub fn local(&self) -> LocalPos {
+        LocalPos(
+            self.0
+                .rem_euclid(IVec3::splat(CHUNK_SIZE as i32))
+                .as_uvec3(),
+        )
+    }
+
+    pub fn split(&self) -> (ChunkPos, LocalPos) {
+        (self.chunk(), self.local())
+    }
+
+    pub fn offset(&self, offset: IVec3) -> Self {
+        Self(self.0 + offset)
+    }
+
+    pub fn center(&self) -> Vec3 {
+        self.0.as_vec3() + Vec3::splat(0.5)
+    }
+
+    pub fn neighbours(&self) -> impl Iterator<Item = BlockPos> {
+        let pos = *self;
+
+        NEIGHBOURS.into_iter().map(move |offset| pos.offset(offset))
+    }
+
+    pub fn aabb(min: BlockPos, max: BlockPos) -> impl Iterator<Item = BlockPos> {
+        iter_aabb(min.0, max.0).map(BlockPos)
+    }
+}
+
+impl ChunkPos {
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Self(IVec3::new(x, y, z))
+    }
+
+    pub fn offset(&self, offset: IVec3) -> Self {
+        Self(self.0 + offset)
+    }
+
+    // World position of the block at the local position 0, 0, 0
+    pub fn origin(&self) -> BlockPos {
+        BlockPos(self.0 * CHUNK_SIZE as i32)
+    }
+
+    pub fn block(&self, local: LocalPos) -> BlockPos {
+        BlockPos(self.origin().0 + local.0.as_ivec3())
+    }
+
+    pub fn neighbours(&self) -> impl Iterator<Item = ChunkPos> {
+        let pos = *self;
+
+        NEIGHBOURS.into_iter().map(move |offset| pos.offset(offset))
+    }
+
+    pub fn aabb(min: ChunkPos, max: ChunkPos) -> impl Iterator<Item = ChunkPos> {
+        iter_aabb(min.0, max.0).map(ChunkPos)
+    }
+
+    // Every chunk touched by the blocks between min and max
+    pub fn span(min: BlockPos, max: BlockPos) -> impl Iterator<Item = ChunkPos> {
+        let (min, max) = (min.0.min(max.0), min.0.max(max.0));
+
+        Self::aabb(BlockPos(min).chunk(), BlockPos(max).chunk())
+    }
+}
+
+impl LocalPos {
+    pub const fn new(x: u32, y: u32, z: u32) -> Self {
+        Self(UVec3::new(x, y, z))
+    }
+
+    pub fn index(&self) -> usize {
+        self.0.x as usize
+            + self.0.y as usize * CHUNK_SIZE
+            + self.0.z as usize * CHUNK_SIZE * CHUNK_SIZE
+    }
+
+    pub fn iter() -> impl Iterator<Item = LocalPos> {
+        iter_aabb(IVec3::ZERO, IVec3::splat(CHUNK_SIZE as i32 - 1))
+            .map(|pos| LocalPos(pos.as_uvec3()))
+    }
+}
+
+impl From<IVec3> for BlockPos {
+    fn from(pos: IVec3) -> Self {
+        Self(pos)
+    }
+}
+
+impl From<Vec3> for BlockPos {
+    fn from(pos: Vec3) -> Self {
+        Self(pos.floor().as_ivec3())
+    }
+}
+
+impl From<IVec3> for ChunkPos {
+    fn from(pos: IVec3) -> Self {
+        Self(pos)
+    }
+}
+
+// Chunk containing the given world position
+impl From<Vec3> for ChunkPos {
+    fn from(pos: Vec3) -> Self {
+        BlockPos::from(pos).chunk()
+    }
+}
+
+impl From<UVec3> for LocalPos {
+    fn from(pos: UVec3) -> Self {
+        Self(pos)
+    }
+}
+
+impl Deref for BlockPos {
+    type Target = IVec3;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Deref for ChunkPos {
+    type Target = IVec3;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Deref for LocalPos {
+    type Target = UVec3;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: i32 = CHUNK_SIZE as i32;
+
+    #[test]
+    fn split_negative_boundaries() {
+        let cases = [
+            (-1, -1, SIZE as u32 - 1),
+            (-SIZE, -1, 0),
+            (-SIZE - 1, -2, SIZE as u32 - 1),
+            (0, 0, 0),
+            (SIZE, 1, 0),
+        ];
+
+        for (x, chunk, local) in cases {
+            let pos = BlockPos::new(x, x, x);
+            let (chunk_pos, local_pos) = pos.split();
+
+            assert_eq!(chunk_pos, ChunkPos::new(chunk, chunk, chunk), "block {}", x);
+            assert_eq!(local_pos, LocalPos::new(local, local, local), "block {}", x);
+            assert_eq!(chunk_pos.block(local_pos), pos, "block {}", x);
+        }
+    }
+
+    #[test]
+    fn chunk_of_negative_world_positions() {
+        assert_eq!(ChunkPos::from(Vec3::splat(-0.5)), ChunkPos::new(-1, -1, -1));
+        assert_eq!(
+            ChunkPos::from(Vec3::splat(-SIZE as f32)),
+            ChunkPos::new(-1, -1, -1)
+        );
+        assert_eq!(
+            ChunkPos::from(Vec3::splat(-SIZE as f32 - 0.5)),
+            ChunkPos::new(-2, -2, -2)
+        );
+        assert_eq!(
+            ChunkPos::new(-1, 0, -2).origin(),
+            BlockPos::new(-SIZE, 0, -2 * SIZE)
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use super::{
+    blocks::Block,
+    chunk::{Chunk, CHUNK_SIZE},
+    coords::BlockPos,
+    VoxelWorld,
+};
+
+// Fluids store their level in the 4 high bits instead of the health : the source level, then decreasing while flowing
+pub const SOURCE_LEVEL: u8 = 8;
+
+// Number of fixed updates between two steps of the simulation
+pub const FLUID_TICK: u32 = 8;
+
+// Maximum number of voxels updated in a single step, the others wait for the next one
+pub const MAX_FLUID_UPDATES: usize = 4096;
+
+// Water below this height is filled when the terrain is generated
+pub const SEA_LEVEL: i32 = CHUNK_SIZE as i32 + 16;
+
+const HORIZONTAL: [IVec3; 4] = [IVec3::NEG_X, IVec3::X, IVec3::NEG_Z, IVec3::Z];
+
+// Cellular automaton over the fluid voxels that may change
+#[derive(Debug, Default)]
+pub struct FluidSimulation {
+    pub active: HashSet<BlockPos>,
+    pub ticks: u32,
+}
+
+fn fluid_level(world: &VoxelWorld, chunks: &Query<&Chunk>, pos: BlockPos) -> Option<u8> {
+    match world.get_block(chunks, pos) {
+        Some((block, level)) if block.is_fluid() => Some(level.clamp(1, SOURCE_LEVEL)),
+        _ => None,
+    }
+}
+
+fn is_empty(world: &VoxelWorld, chunks: &Query<&Chunk>, pos: BlockPos) -> bool {
+    world
+        .get_block(chunks, pos)
+        .is_some_and(|(block, _)| block == Block::Air)
+}
+
+// Keep the highest level when several voxels flow into the same one
+fn flow_into(changes: &mut HashMap<BlockPos, (Block, u8)>, pos: BlockPos, level: u8) {
+    let entry = changes.entry(pos).or_insert((Block::Water, level));
+
+    entry.1 = entry.1.max(level);
+}
+
+impl FluidSimulation {
+    // The voxel and its neighbours are checked during the next step
+    pub fn activate(&mut self, pos: BlockPos) {
+        self.active.insert(pos);
+        self.active.extend(pos.neighbours());
+    }
+
+    // Run one step of the simulation and return the voxels to change
+    pub fn step(
+        &mut self,
+        world: &VoxelWorld,
+        chunks: &Query<&Chunk>,
+    ) -> Vec<(BlockPos, Block, u8)> {
+        let mut active = self.active.drain().collect::<Vec<_>>();
+
+        // Sorted so the simulation doesn't depend on the iteration order of the set
+        active.sort_by_key(|pos| (pos.y, pos.z, pos.x));
+
+        if active.len() > MAX_FLUID_UPDATES {
+            self.active.extend(active.drain(MAX_FLUID_UPDATES..));
+        }
+
+        let mut changes = HashMap::new();
+
+        for pos in active {
+            let Some(mut level) = fluid_level(world, chunks, pos) else {
+                continue;
+            };
+
+            let below = pos.offset(IVec3::NEG_Y);
+
+            if level < SOURCE_LEVEL {
+                let neighbours = HORIZONTAL
+                    .iter()
+                    .filter_map(|offset| fluid_level(world, chunks, pos.offset(*offset)))
+                    .collect::<Vec<_>>();
+
+                let sources = neighbours
+                    .iter()
+                    .filter(|level| **level == SOURCE_LEVEL)
+                    .count();
+
+                let supported = match world.get_block(chunks, below) {
+                    Some((Block::Air, _)) | None => false,
+                    Some((block, level)) => !block.is_fluid() || level >= SOURCE_LEVEL,
+                };
+
+                // Flowing water between two sources on a solid ground becomes a source, so craters fill up
+                let expected = if sources >= 2 && supported {
+                    SOURCE_LEVEL
+                } else if fluid_level(world, chunks, pos.offset(IVec3::Y)).is_some() {
+                    SOURCE_LEVEL - 1
+                } else {
+                    neighbours.iter().max().unwrap_or(&0).saturating_sub(1)
+                };
+
+                if expected == 0 {
+                    changes.insert(pos, (Block::Air, 15));
+
+                    continue;
+                }
+
+                if expected != level {
+                    changes.insert(pos, (Block::Water, expected));
+
+                    level = expected;
+                }
+            }
+
+            // Falling water doesn't spread until it reaches the ground or a source
+            if is_empty(world, chunks, below) {
+                flow_into(&mut changes, below, SOURCE_LEVEL - 1);
+            } else if fluid_level(world, chunks, below).is_none_or(|below| below >= SOURCE_LEVEL)
+                && level > 1
+            {
+                for offset in HORIZONTAL {
+                    let neighbour = pos.offset(offset);
+
+                    if is_empty(world, chunks, neighbour) {
+                        flow_into(&mut changes, neighbour, level - 1);
+                    }
+                }
+            }
+        }
+
+        changes
+            .into_iter()
+            .map(|(pos, (block, level))| (pos, block, level))
+            .collect()
+    }
+}
@@ -0,0 +1,330 @@
+use bevy::{prelude::*, utils::HashSet};
+
+use super::{
+    blocks::Block,
+    chunk::{Chunk, TerrainGenerated, CHUNK_SIZE},
+    coords::{BlockPos, LocalPos},
+    gravity::{FallingBlock, GRAVITY, MAX_FALL_SPEED},
+    model::VoxelModel,
+    VoxelWorld,
+};
+
+// Islands bigger than this are considered anchored, so the flood fill stops early inside the terrain
+pub const MAX_FRAGMENT_VOLUME: usize = 2048;
+
+// Fragments hitting the world faster than this break into debris
+pub const SHATTER_SPEED: f32 = 16.0;
+
+// Fragments slower than this for the rest time are merged back into the world
+pub const REST_SPEED: f32 = 0.5;
+pub const REST_TIME: f32 = 0.5;
+
+pub const MAX_SHATTER_DEBRIS: usize = 64;
+
+const RESTITUTION: f32 = 0.2;
+const FRICTION: f32 = 0.6;
+const TIPPING: f32 = 6.0;
+
+// A group of voxels cut from the world, stored in its own chunk and moved as a rigid body
+#[derive(Debug, Component)]
+pub struct VoxelFragment {
+    pub world: Entity,
+    pub voxels: Vec<LocalPos>,
+
+    // Center of mass in the chunk and in the world
+    pub center: Vec3,
+    pub position: Vec3,
+
+    pub velocity: Vec3,
+    pub angular_velocity: Vec3,
+    pub rest: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FragmentState {
+    Moving,
+    Shatter,
+    Merge,
+}
+
+fn is_solid(world: &VoxelWorld, chunks: &Query<&Chunk>, pos: BlockPos) -> bool {
+    world
+        .get_block(chunks, pos)
+        .is_some_and(|(block, _)| block.is_solid())
+}
+
+// Solid voxels connected to the start that are not anchored to the world. Voxels in unloaded chunks or in chunks
+// whose terrain is not generated yet anchor the island, as well as islands that don't fit in a chunk. Every voxel reached is added to the visited set, and
+// later searches reaching one of them stop, as it belongs to an island already found or to an anchored structure.
+pub fn find_island(
+    world: &VoxelWorld,
+    chunks: &Query<&Chunk>,
+    generated: &Query<(), With<TerrainGenerated>>,
+    start: BlockPos,
+    visited: &mut HashSet<BlockPos>,
+) -> Option<Vec<BlockPos>> {
+    if visited.contains(&start) || !is_solid(world, chunks, start) {
+        return None;
+    }
+
+    let mut reached = HashSet::new();
+    let island = flood_island(world, chunks, generated, start, visited, &mut reached);
+
+    visited.extend(reached);
+
+    island
+}
+
+fn flood_island(
+    world: &VoxelWorld,
+    chunks: &Query<&Chunk>,
+    generated: &Query<(), With<TerrainGenerated>>,
+    start: BlockPos,
+    visited: &HashSet<BlockPos>,
+    reached: &mut HashSet<BlockPos>,
+) -> Option<Vec<BlockPos>> {
+    let mut island = vec![start];
+    let mut stack = vec![start];
+    let (mut min, mut max) = (start.0, start.0);
+
+    reached.insert(start);
+
+    while let Some(pos) = stack.pop() {
+        for neighbour in pos.neighbours() {
+            if reached.contains(&neighbour) {
+                continue;
+            }
+
+            // The chunks waiting for their terrain read as air
+            if !generated.contains(*world.chunks.get(&neighbour.chunk())?) {
+                return None;
+            }
+
+            let (block, _) = world.get_block(chunks, neighbour)?;
+
+            if !block.is_solid() {
+                continue;
+            }
+
+            if visited.contains(&neighbour) {
+                return None;
+            }
+
+            reached.insert(neighbour);
+            island.push(neighbour);
+            stack.push(neighbour);
+
+            min = min.min(neighbour.0);
+            max = max.max(neighbour.0);
+
+            if island.len() > MAX_FRAGMENT_VOLUME || (max - min).max_element() >= CHUNK_SIZE as i32
+            {
+                return None;
+            }
+        }
+    }
+
+    Some(island)
+}
+
+impl VoxelFragment {
+    // Copy the voxels of the island into a chunk placed at its minimum corner
+    pub fn from_island(
+        world_id: Entity,
+        world: &VoxelWorld,
+        chunks: &Query<&Chunk>,
+        island: &[BlockPos],
+    ) -> (Self, Chunk, Transform) {
+        let min = island.iter().fold(IVec3::MAX, |min, pos| min.min(pos.0));
+
+        let mut chunk = VoxelModel::empty_chunk();
+        let mut voxels = Vec::new();
+
+        for pos in island {
+            let Some((block, health)) = world.get_block(chunks, *pos) else {
+                continue;
+            };
+
+            let local = LocalPos((pos.0 - min).as_uvec3());
+
+            match chunk.set_block(
+                local.x as usize,
+                local.y as usize,
+                local.z as usize,
+                block,
+                health,
+            ) {
+                Ok(_) => voxels.push(local),
+                Err(error) => eprintln!("{}", error),
+            }
+        }
+
+        let center = voxels
+            .iter()
+            .map(|local| local.as_vec3() + 0.5)
+            .sum::<Vec3>()
+            / voxels.len().max(1) as f32;
+
+        let fragment = Self {
+            world: world_id,
+            voxels,
+            center,
+            position: min.as_vec3() + center,
+            velocity: Vec3::ZERO,
+            angular_velocity: Vec3::ZERO,
+            rest: 0.0,
+        };
+
+        (fragment, chunk, Transform::from_translation(min.as_vec3()))
+    }
+
+    fn place(&self, transform: &mut Transform, rotation: Quat) {
+        transform.rotation = rotation;
+        transform.translation = self.position - rotation * self.center;
+    }
+
+    // World positions of the centers of the voxels of the fragment
+    pub fn voxel_centers<'a>(
+        &'a self,
+        transform: &'a Transform,
+    ) -> impl Iterator<Item = Vec3> + 'a {
+        self.voxels
+            .iter()
+            .map(|local| transform.transform_point(local.as_vec3() + 0.5))
+    }
+
+    // Move and rotate the fragment, bouncing on the world and tipping over around the contact points
+    pub fn step(
+        &mut self,
+        transform: &mut Transform,
+        world: &VoxelWorld,
+        chunks: &Query<&Chunk>,
+        delta: f32,
+    ) -> FragmentState {
+        self.velocity.y = (self.velocity.y - GRAVITY * delta).max(-MAX_FALL_SPEED);
+
+        let rotation = transform.rotation;
+        let turned = Quat::from_scaled_axis(self.angular_velocity * delta) * rotation;
+
+        // Horizontal moves are tested one axis at a time on the leading side of the voxels, so the fragment
+        // bounces off the walls instead of going through them
+        for axis in [0, 2] {
+            let motion = self.velocity[axis] * delta;
+
+            if motion == 0.0 {
+                continue;
+            }
+
+            let previous = self.position[axis];
+
+            self.position[axis] += motion;
+            self.place(transform, turned);
+
+            let mut side = Vec3::ZERO;
+            side[axis] = 0.5 * motion.signum();
+
+            let blocked = self
+                .voxel_centers(transform)
+                .any(|center| is_solid(world, chunks, BlockPos::from(center + side)));
+
+            if blocked {
+                if self.velocity.length() > SHATTER_SPEED {
+                    return FragmentState::Shatter;
+                }
+
+                self.position[axis] = previous;
+                self.velocity[axis] *= -RESTITUTION;
+            }
+        }
+
+        let position = self.position;
+
+        self.position.y += self.velocity.y * delta;
+        self.place(transform, turned);
+
+        // The bottom of the voxels is tested so the fragment lands on the world instead of sinking in it
+        let contacts = self
+            .voxel_centers(transform)
+            .map(|center| center - Vec3::Y * 0.5)
+            .filter(|bottom| is_solid(world, chunks, BlockPos::from(*bottom)))
+            .collect::<Vec<_>>();
+
+        if contacts.is_empty() {
+            self.rest = 0.0;
+
+            return FragmentState::Moving;
+        }
+
+        if self.velocity.length() > SHATTER_SPEED {
+            return FragmentState::Shatter;
+        }
+
+        self.position = position;
+        self.place(transform, rotation);
+
+        let contact = contacts.iter().sum::<Vec3>() / contacts.len() as f32;
+
+        self.velocity.y = -self.velocity.y.min(0.0) * RESTITUTION;
+        self.velocity.x *= FRICTION;
+        self.velocity.z *= FRICTION;
+
+        // The world pushes up at the contact, so the fragment falls on the side of its center of mass
+        self.angular_velocity = self.angular_velocity * FRICTION
+            + (contact - self.position).cross(Vec3::Y) * TIPPING * delta;
+
+        if self.velocity.length() < REST_SPEED && self.angular_velocity.length() < REST_SPEED {
+            self.rest += delta;
+        } else {
+            self.rest = 0.0;
+        }
+
+        match self.rest >= REST_TIME {
+            true => FragmentState::Merge,
+            false => FragmentState::Moving,
+        }
+    }
+
+    // Blocks to write back into the world, voxels landing in solid blocks are lost
+    pub fn merged_blocks(
+        &self,
+        transform: &Transform,
+        chunk: &Chunk,
+        world: &VoxelWorld,
+        chunks: &Query<&Chunk>,
+    ) -> Vec<(BlockPos, Block, u8)> {
+        self.voxels
+            .iter()
+            .zip(self.voxel_centers(transform))
+            .filter_map(|(local, center)| {
+                let pos = BlockPos::from(center);
+
+                if is_solid(world, chunks, pos) {
+                    return None;
+                }
+
+                let block = chunk.blocks[local.index()];
+
+                Some((pos, Block::from(block & 0b1111), block >> 4))
+            })
+            .collect()
+    }
+
+    // Debris falling from the voxels of the fragment, spread over the whole fragment when it is too big
+    pub fn debris(&self, transform: &Transform) -> Vec<(FallingBlock, Vec3)> {
+        let step = self.voxels.len().div_ceil(MAX_SHATTER_DEBRIS).max(1);
+
+        self.voxel_centers(transform)
+            .step_by(step)
+            .map(|center| {
+                let debris = FallingBlock {
+                    world: self.world,
+                    block: Block::Debris,
+                    health: 15,
+                    velocity: 0.0,
+                };
+
+                (debris, BlockPos::from(center).center())
+            })
+            .collect()
+    }
+}
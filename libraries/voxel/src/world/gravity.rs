@@ -0,0 +1,96 @@
+use bevy::{prelude::*, utils::HashSet};
+
+use super::{blocks::Block, chunk::Chunk, coords::BlockPos, VoxelWorld};
+
+pub const GRAVITY: f32 = 20.0;
+pub const MAX_FALL_SPEED: f32 = 40.0;
+
+// Falling blocks are removed when they go below this height without landing
+pub const MIN_FALL_HEIGHT: f32 = -64.0;
+
+// A gravity block that lost its support, it becomes a block again where it lands
+#[derive(Debug, Component)]
+pub struct FallingBlock {
+    pub world: Entity,
+    pub block: Block,
+    pub health: u8,
+    pub velocity: f32,
+}
+
+// Blocks that don't hold a gravity block, fluids included
+pub fn is_unsupporting(world: &VoxelWorld, chunks: &Query<&Chunk>, pos: BlockPos) -> bool {
+    world
+        .get_block(chunks, pos)
+        .is_some_and(|(block, _)| block == Block::Air || block.is_fluid())
+}
+
+// First voxel from the position upward that a landing block can replace, air or fluid. The landed positions are
+// the ones taken by blocks during the tick, whose modifications are not applied yet.
+pub fn landing_spot(
+    world: &VoxelWorld,
+    chunks: &Query<&Chunk>,
+    landed: &HashSet<BlockPos>,
+    mut pos: BlockPos,
+) -> Option<BlockPos> {
+    loop {
+        let (block, _) = world.get_block(chunks, pos)?;
+
+        if (block == Block::Air || block.is_fluid()) && !landed.contains(&pos) {
+            return Some(pos);
+        }
+
+        pos = pos.offset(IVec3::Y);
+    }
+}
+
+// Gravity blocks at the given positions that are not supported anymore
+pub fn unsupported_blocks(
+    world: &VoxelWorld,
+    chunks: &Query<&Chunk>,
+    positions: impl IntoIterator<Item = BlockPos>,
+) -> Vec<(BlockPos, Block, u8)> {
+    positions
+        .into_iter()
+        .filter_map(|pos| match world.get_block(chunks, pos) {
+            Some((block, health))
+                if block.has_gravity()
+                    && is_unsupporting(world, chunks, pos.offset(IVec3::NEG_Y)) =>
+            {
+                Some((pos, block, health))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+impl FallingBlock {
+    // Move the block and return the position where it lands, if it does
+    pub fn fall(
+        &mut self,
+        transform: &mut Transform,
+        world: &VoxelWorld,
+        chunks: &Query<&Chunk>,
+        delta: f32,
+    ) -> Option<BlockPos> {
+        self.velocity = (self.velocity - GRAVITY * delta).max(-MAX_FALL_SPEED);
+
+        // Voxels are crossed by the bottom of the block
+        let from = BlockPos::from(transform.translation - Vec3::Y * 0.5);
+        transform.translation.y += self.velocity * delta;
+        let to = BlockPos::from(transform.translation - Vec3::Y * 0.5);
+
+        // Check every voxel crossed during the step so fast blocks don't go through thin floors
+        for y in (to.y..from.y).rev() {
+            let pos = BlockPos::new(from.x, y, from.z);
+
+            if world
+                .get_block(chunks, pos)
+                .is_some_and(|(block, _)| block.is_solid())
+            {
+                return Some(pos.offset(IVec3::Y));
+            }
+        }
+
+        None
+    }
+}
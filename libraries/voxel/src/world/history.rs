@@ -0,0 +1,149 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{blocks::Block, chunk::Chunk, coords::BlockPos, VoxelWorld};
+
+pub const HISTORY_LIMIT: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+pub struct VoxelEdit {
+    pub pos: BlockPos,
+    pub before: (Block, u8),
+    pub after: (Block, u8),
+}
+
+// A group of edits that are undone and redone together
+#[derive(Debug, Default, Clone)]
+pub struct EditTransaction {
+    // Given by the history when the transaction is committed
+    pub id: u64,
+    pub edits: Vec<VoxelEdit>,
+
+    indices: HashMap<BlockPos, usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct EditHistory {
+    pub undo: Vec<EditTransaction>,
+    pub redo: Vec<EditTransaction>,
+
+    last_id: u64,
+}
+
+// How a block of a chunk modification made by the history is applied
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistoryEntry {
+    // Set by a committed transaction, the content it replaces becomes the before of the edit
+    Commit(u64),
+    // Set by an undo or a redo, only if the voxel still holds this content so later edits are kept
+    Restore((Block, u8)),
+}
+
+#[derive(Debug, Event)]
+pub enum HistoryAction {
+    Undo,
+    Redo,
+}
+
+impl EditTransaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    // Blocks in unloaded chunks are ignored. The before is only a guess as other modifications may still be
+    // pending, it is replaced by the content actually replaced once the transaction is committed and applied.
+    pub fn set_block(
+        &mut self,
+        world: &VoxelWorld,
+        chunks: &Query<&Chunk>,
+        pos: BlockPos,
+        block: Block,
+        health: u8,
+    ) {
+        if let Some(index) = self.indices.get(&pos) {
+            self.edits[*index].after = (block, health);
+
+            return;
+        }
+
+        if let Some(before) = world.get_block(chunks, pos) {
+            self.indices.insert(pos, self.edits.len());
+            self.edits.push(VoxelEdit {
+                pos,
+                before,
+                after: (block, health),
+            });
+        }
+    }
+
+    pub fn set_blocks(
+        &mut self,
+        world: &VoxelWorld,
+        chunks: &Query<&Chunk>,
+        blocks: impl IntoIterator<Item = (BlockPos, Block, u8)>,
+    ) {
+        for (pos, block, health) in blocks {
+            self.set_block(world, chunks, pos, block, health);
+        }
+    }
+
+    pub fn apply(&self, world: &VoxelWorld, commands: &mut Commands) {
+        world.set_history_blocks(
+            commands,
+            self.edits.iter().map(|edit| {
+                let entry = HistoryEntry::Restore(edit.before);
+
+                (edit.pos, edit.after.0, edit.after.1, entry)
+            }),
+        );
+    }
+
+    pub fn revert(&self, world: &VoxelWorld, commands: &mut Commands) {
+        world.set_history_blocks(
+            commands,
+            self.edits.iter().map(|edit| {
+                let entry = HistoryEntry::Restore(edit.after);
+
+                (edit.pos, edit.before.0, edit.before.1, entry)
+            }),
+        );
+    }
+
+    fn record(&mut self, pos: BlockPos, before: (Block, u8)) {
+        if let Some(index) = self.indices.get(&pos) {
+            self.edits[*index].before = before;
+        }
+    }
+}
+
+impl EditHistory {
+    pub fn next_id(&mut self) -> u64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    // Set the content that a committed transaction replaced, once its blocks are applied to the chunk
+    pub fn record(&mut self, id: u64, pos: BlockPos, before: (Block, u8)) {
+        let transaction = self
+            .undo
+            .iter_mut()
+            .chain(self.redo.iter_mut())
+            .find(|transaction| transaction.id == id);
+
+        if let Some(transaction) = transaction {
+            transaction.record(pos, before);
+        }
+    }
+
+    pub fn push(&mut self, transaction: EditTransaction) {
+        self.redo.clear();
+        self.undo.push(transaction);
+
+        if self.undo.len() > HISTORY_LIMIT {
+            self.undo.remove(0);
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, utils::HashSet};
+
+use super::{
+    blocks::Block,
+    chunk::{Chunk, TerrainGenerated, CHUNK_SIZE},
+    coords::{BlockPos, ChunkPos, LocalPos, NEIGHBOURS},
+    VoxelWorld,
+};
+
+pub const MAX_LIGHT: u8 = 15;
+
+pub type LightChunks<'w, 's> = Query<'w, 's, (&'static mut Chunk, Has<TerrainGenerated>)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightChannel {
+    Sky,
+    Block,
+}
+
+// Flood fill of the sky and block light across the chunks of a world. Lights are removed before being added back,
+// so that an edit only touches the voxels that were lit by it.
+pub struct LightEngine<'a, 'w, 's> {
+    world: &'a VoxelWorld,
+    chunks: &'a mut LightChunks<'w, 's>,
+
+    additions: VecDeque<(BlockPos, LightChannel)>,
+    removals: VecDeque<(BlockPos, LightChannel, u8)>,
+
+    // Chunks whose light or apron changed
+    pub updated: HashSet<Entity>,
+}
+
+impl LightChannel {
+    pub fn get(&self, light: u8) -> u8 {
+        match self {
+            LightChannel::Sky => light >> 4,
+            LightChannel::Block => light & 0b1111,
+        }
+    }
+
+    pub fn with(&self, light: u8, level: u8) -> u8 {
+        match self {
+            LightChannel::Sky => light & 0b1111 | level << 4,
+            LightChannel::Block => light & 0b1111_0000 | level,
+        }
+    }
+}
+
+impl<'a, 'w, 's> LightEngine<'a, 'w, 's> {
+    pub fn new(world: &'a VoxelWorld, chunks: &'a mut LightChunks<'w, 's>) -> Self {
+        Self {
+            world,
+            chunks,
+            additions: VecDeque::new(),
+            removals: VecDeque::new(),
+            updated: HashSet::new(),
+        }
+    }
+
+    // Chunks that are not generated yet don't hold any light and are not lit
+    fn generated(&self, pos: ChunkPos) -> Option<Entity> {
+        let entity = *self.world.chunks.get(&pos)?;
+
+        match self.chunks.get(entity) {
+            Ok((_, true)) => Some(entity),
+            _ => None,
+        }
+    }
+
+    pub fn get_block(&self, pos: BlockPos) -> Option<Block> {
+        let (chunk_pos, local) = pos.split();
+        let (chunk, _) = self.chunks.get(self.generated(chunk_pos)?).ok()?;
+
+        Some(Block::from(chunk.blocks[local.index()] & 0b1111))
+    }
+
+    pub fn get_light(&self, pos: BlockPos) -> Option<u8> {
+        let (chunk_pos, local) = pos.split();
+        let (chunk, _) = self.chunks.get(self.generated(chunk_pos)?).ok()?;
+
+        Some(chunk.get_light(local.as_ivec3()))
+    }
+
+    // Store the light in its chunk and in the aprons of the neighbours around it
+    fn set_light(&mut self, pos: BlockPos, light: u8) {
+        let (chunk_pos, local) = pos.split();
+
+        for offset in ChunkPos::aabb(ChunkPos::new(-1, -1, -1), ChunkPos::new(1, 1, 1)) {
+            let padded = local.as_ivec3() - offset.0 * CHUNK_SIZE as i32;
+
+            if padded.min_element() < -1 || padded.max_element() > CHUNK_SIZE as i32 {
+                continue;
+            }
+
+            let Some(entity) = self.world.chunks.get(&chunk_pos.offset(offset.0)).cloned() else {
+                continue;
+            };
+
+            if let Ok((mut chunk, _)) = self.chunks.get_mut(entity) {
+                if chunk.get_light(padded) != light {
+                    chunk.set_light(padded, light);
+
+                    self.updated.insert(entity);
+                }
+            }
+        }
+    }
+
+    // Voxels right under a chunk that is not generated receive the full sky light
+    fn is_open_sky(&self, pos: BlockPos) -> bool {
+        self.generated(pos.offset(IVec3::Y).chunk()).is_none()
+    }
+
+    fn remove(&mut self, pos: BlockPos, channel: LightChannel) {
+        let Some(light) = self.get_light(pos) else {
+            return;
+        };
+
+        let level = channel.get(light);
+
+        if level > 0 {
+            self.set_light(pos, channel.with(light, 0));
+            self.removals.push_back((pos, channel, level));
+        }
+    }
+
+    fn add(&mut self, pos: BlockPos, channel: LightChannel, level: u8) {
+        let Some(light) = self.get_light(pos) else {
+            return;
+        };
+
+        if level > channel.get(light) {
+            self.set_light(pos, channel.with(light, level));
+        }
+
+        self.additions.push_back((pos, channel));
+    }
+
+    // Light a freshly generated chunk from the sky, its emissive blocks and the light of its neighbours
+    pub fn relight_chunk(&mut self, pos: ChunkPos) {
+        let Some(entity) = self.generated(pos) else {
+            return;
+        };
+
+        // The top of the chunk below was lit as if it was under the open sky
+        let below = pos.offset(IVec3::NEG_Y);
+        if self.generated(below).is_some() {
+            for z in 0..CHUNK_SIZE as u32 {
+                for x in 0..CHUNK_SIZE as u32 {
+                    let pos = below.block(LocalPos::new(x, CHUNK_SIZE as u32 - 1, z));
+
+                    self.remove(pos, LightChannel::Sky);
+                }
+            }
+        }
+
+        // Column down propagation of the sky light
+        let above = pos.offset(IVec3::Y);
+        let open = self.generated(above).is_none();
+
+        let mut levels = vec![0u8; CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE];
+
+        if let Ok((chunk, _)) = self.chunks.get(entity) {
+            for z in 0..CHUNK_SIZE as u32 {
+                for x in 0..CHUNK_SIZE as u32 {
+                    let mut sky = open
+                        || self
+                            .get_light(above.block(LocalPos::new(x, 0, z)))
+                            .is_some_and(|light| LightChannel::Sky.get(light) == MAX_LIGHT);
+
+                    for y in (0..CHUNK_SIZE as u32).rev() {
+                        let local = LocalPos::new(x, y, z);
+                        let block = Block::from(chunk.blocks[local.index()] & 0b1111);
+
+                        sky &= !block.is_opaque();
+
+                        let sky_level = if sky { MAX_LIGHT } else { 0 };
+
+                        levels[local.index()] = LightChannel::Block
+                            .with(LightChannel::Sky.with(0, sky_level), block.light_emission());
+                    }
+                }
+            }
+        }
+
+        for local in LocalPos::iter() {
+            let light = levels[local.index()];
+            let block_pos = pos.block(local);
+
+            match Chunk::is_on_border(local) {
+                true => self.set_light(block_pos, light),
+                false => {
+                    if let Ok((mut chunk, _)) = self.chunks.get_mut(entity) {
+                        chunk.set_light(local.as_ivec3(), light);
+                    }
+                }
+            }
+
+            if LightChannel::Sky.get(light) == MAX_LIGHT {
+                self.additions.push_back((block_pos, LightChannel::Sky));
+            }
+
+            if LightChannel::Block.get(light) > 0 {
+                self.additions.push_back((block_pos, LightChannel::Block));
+            }
+        }
+
+        self.updated.insert(entity);
+
+        // Fill the apron with the light of the neighbours, missing ones are considered under the open sky
+        let origin = pos.origin();
+        for block_pos in BlockPos::aabb(
+            origin.offset(IVec3::NEG_ONE),
+            origin.offset(IVec3::splat(CHUNK_SIZE as i32)),
+        ) {
+            if block_pos.chunk() == pos {
+                continue;
+            }
+
+            let light = self
+                .get_light(block_pos)
+                .unwrap_or(LightChannel::Sky.with(0, MAX_LIGHT));
+
+            if let Ok((mut chunk, _)) = self.chunks.get_mut(entity) {
+                chunk.set_light(block_pos.0 - origin.0, light);
+            }
+
+            // The neighbours spread their light inside the chunk
+            if NEIGHBOURS
+                .iter()
+                .any(|normal| block_pos.offset(*normal).chunk() == pos)
+            {
+                self.additions.push_back((block_pos, LightChannel::Sky));
+                self.additions.push_back((block_pos, LightChannel::Block));
+            }
+        }
+    }
+
+    // Update the light around a voxel whose content changed
+    pub fn update_block(&mut self, pos: BlockPos) {
+        let Some(block) = self.get_block(pos) else {
+            return;
+        };
+
+        self.remove(pos, LightChannel::Sky);
+        self.remove(pos, LightChannel::Block);
+
+        if block.light_emission() > 0 {
+            self.add(pos, LightChannel::Block, block.light_emission());
+        }
+
+        if !block.is_opaque() && self.is_open_sky(pos) {
+            self.add(pos, LightChannel::Sky, MAX_LIGHT);
+        }
+
+        for neighbour in pos.neighbours() {
+            self.additions.push_back((neighbour, LightChannel::Sky));
+            self.additions.push_back((neighbour, LightChannel::Block));
+        }
+    }
+
+    pub fn propagate(&mut self) {
+        while let Some((pos, channel, level)) = self.removals.pop_front() {
+            for normal in NEIGHBOURS {
+                let neighbour = pos.offset(normal);
+
+                let Some(light) = self.get_light(neighbour) else {
+                    continue;
+                };
+
+                let neighbour_level = channel.get(light);
+
+                if neighbour_level == 0 {
+                    continue;
+                }
+
+                // The full sky light goes down without attenuation, so it depends on the voxel above
+                let dependent = neighbour_level < level
+                    || (channel == LightChannel::Sky
+                        && normal == IVec3::NEG_Y
+                        && level == MAX_LIGHT);
+
+                match dependent {
+                    true => {
+                        self.set_light(neighbour, channel.with(light, 0));
+                        self.removals
+                            .push_back((neighbour, channel, neighbour_level));
+                    }
+                    false => self.additions.push_back((neighbour, channel)),
+                }
+            }
+        }
+
+        while let Some((pos, channel)) = self.additions.pop_front() {
+            let Some(light) = self.get_light(pos) else {
+                continue;
+            };
+
+            let level = channel.get(light);
+
+            if level == 0 {
+                continue;
+            }
+
+            for normal in NEIGHBOURS {
+                let neighbour = pos.offset(normal);
+
+                if self
+                    .get_block(neighbour)
+                    .is_none_or(|block| block.is_opaque())
+                {
+                    continue;
+                }
+
+                let Some(neighbour_light) = self.get_light(neighbour) else {
+                    continue;
+                };
+
+                let neighbour_level = match channel == LightChannel::Sky
+                    && normal == IVec3::NEG_Y
+                    && level == MAX_LIGHT
+                {
+                    true => MAX_LIGHT,
+                    false => level - 1,
+                };
+
+                if neighbour_level > channel.get(neighbour_light) {
+                    self.set_light(neighbour, channel.with(neighbour_light, neighbour_level));
+                    self.additions.push_back((neighbour, channel));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use bevy::prelude::*;
+
+use super::{
+    chunk::{Chunk, CHUNK_SIZE},
+    coords::ChunkPos,
+    light::{LightChannel, MAX_LIGHT},
+    schematic::Schematic,
+    vox,
+};
+
+// Chunk carried by an entity instead of being part of a world, like props, vehicles, weapons or fragments.
+// It is meshed with the transform of its entity and is not generated nor lit by the world.
+#[derive(Debug, Default, Clone, Copy, Component)]
+pub struct VoxelModel;
+
+#[derive(Debug, Event)]
+pub struct SpawnVoxModel {
+    pub path: String,
+    pub transform: Transform,
+}
+
+impl VoxelModel {
+    // Empty chunk lit as if it was under the open sky
+    pub fn empty_chunk() -> Chunk {
+        let mut chunk = Chunk::new(ChunkPos::default());
+
+        chunk.light.fill(LightChannel::Sky.with(0, MAX_LIGHT));
+
+        chunk
+    }
+
+    pub fn from_schematic(schematic: &Schematic) -> eyre::Result<Chunk> {
+        if schematic.size.max_element() > CHUNK_SIZE as u32 {
+            return Err(eyre::eyre!(format!(
+                "Model of size {:?} doesn't fit in a chunk",
+                schematic.size
+            )));
+        }
+
+        let mut chunk = Self::empty_chunk();
+
+        for z in 0..schematic.size.z {
+            for y in 0..schematic.size.y {
+                for x in 0..schematic.size.x {
+                    let (block, health) = schematic.get(UVec3::new(x, y, z));
+
+                    chunk.set_block(x as usize, y as usize, z as usize, block, health)?;
+                }
+            }
+        }
+
+        Ok(chunk)
+    }
+
+    pub fn load_vox(path: impl AsRef<std::path::Path>) -> eyre::Result<Chunk> {
+        Self::from_schematic(&vox::import_vox(path)?)
+    }
+}
@@ -0,0 +1,70 @@
+use bevy::prelude::*;
+
+use super::{blocks::Block, chunk::Chunk, coords::BlockPos, VoxelWorld};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoxelHit {
+    pub pos: BlockPos,
+    pub block: Block,
+    pub point: Vec3,
+    // Normal of the face entered by the ray
+    pub normal: IVec3,
+    pub distance: f32,
+}
+
+// Walk the voxels crossed by the ray until a solid one is found, fluids are crossed
+pub fn raycast(
+    world: &VoxelWorld,
+    chunks: &Query<&Chunk>,
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+) -> Option<VoxelHit> {
+    let direction = direction.try_normalize()?;
+
+    let mut pos = BlockPos::from(origin);
+    let step = direction.signum().as_ivec3();
+
+    // Distance along the ray to cross one voxel, and to reach the next boundary on each axis
+    let delta = direction.recip().abs();
+    let mut next = Vec3::select(
+        direction.cmpgt(Vec3::ZERO),
+        (pos.as_vec3() + 1.0 - origin) * delta,
+        (origin - pos.as_vec3()) * delta,
+    );
+
+    // Axes the ray is parallel to are never crossed
+    next = Vec3::select(direction.cmpeq(Vec3::ZERO), Vec3::INFINITY, next);
+
+    let mut normal = IVec3::ZERO;
+    let mut distance = 0.0;
+
+    while distance <= max_distance {
+        if let Some((block, _)) = world.get_block(chunks, pos) {
+            if block.is_solid() {
+                return Some(VoxelHit {
+                    pos,
+                    block,
+                    point: origin + direction * distance,
+                    normal,
+                    distance,
+                });
+            }
+        }
+
+        let axis = match (next.x < next.y, next.x < next.z, next.y < next.z) {
+            (true, true, _) => 0,
+            (false, _, true) => 1,
+            _ => 2,
+        };
+
+        distance = next[axis];
+        next[axis] += delta[axis];
+        pos.0[axis] += step[axis];
+
+        normal = IVec3::ZERO;
+        normal[axis] = -step[axis];
+    }
+
+    None
+}
@@ -0,0 +1,234 @@
+use std::{fs::File, io::Read, io::Write, path::Path};
+
+use bevy::prelude::*;
+
+use super::{blocks::Block, chunk::Chunk, coords::BlockPos, VoxelWorld};
+
+const SCHEMATIC_MAGIC: &[u8; 4] = b"VXSC";
+const SCHEMATIC_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+#[derive(Debug, Clone)]
+pub struct Schematic {
+    pub size: UVec3,
+
+    // Same layout as the chunk blocks : 4bits for the block type and 4bits for the block health
+    pub blocks: Vec<u8>,
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct SchematicClipboard {
+    pub schematic: Option<Schematic>,
+}
+
+#[derive(Debug, Event)]
+pub enum ClipboardAction {
+    Copy { min: BlockPos, max: BlockPos },
+    Paste { origin: BlockPos, replace_air: bool },
+    Rotate { axis: Axis, quarter_turns: u32 },
+    Mirror { axis: Axis },
+    Save { path: String },
+    Load { path: String },
+}
+
+impl Schematic {
+    pub fn new(size: UVec3) -> eyre::Result<Self> {
+        Ok(Self {
+            size,
+            blocks: vec![15 << 4 | Block::Air.as_u8(); Self::volume(size)?],
+        })
+    }
+
+    fn volume(size: UVec3) -> eyre::Result<usize> {
+        (size.x as usize)
+            .checked_mul(size.y as usize)
+            .and_then(|volume| volume.checked_mul(size.z as usize))
+            .ok_or_else(|| eyre::eyre!(format!("Schematic of size {:?} is too large", size)))
+    }
+
+    pub fn copy(
+        world: &VoxelWorld,
+        chunks: &Query<&Chunk>,
+        min: BlockPos,
+        max: BlockPos,
+    ) -> eyre::Result<Self> {
+        let origin = min.min(*max);
+
+        let mut schematic = Self::new((min.max(*max) - origin + IVec3::ONE).as_uvec3())?;
+
+        for pos in BlockPos::aabb(min, max) {
+            if let Some((block, health)) = world.get_block(chunks, pos) {
+                schematic.set((pos.0 - origin).as_uvec3(), block, health);
+            }
+        }
+
+        Ok(schematic)
+    }
+
+    fn index(&self, pos: UVec3) -> usize {
+        (pos.x + pos.y * self.size.x + pos.z * self.size.x * self.size.y) as usize
+    }
+
+    pub fn get(&self, pos: UVec3) -> (Block, u8) {
+        let data = self.blocks[self.index(pos)];
+
+        (Block::from(data & 0b1111), data >> 4)
+    }
+
+    pub fn set(&mut self, pos: UVec3, block: Block, health: u8) {
+        let index = self.index(pos);
+
+        self.blocks[index] = block.as_u8() | health << 4;
+    }
+
+    pub fn rotate(&self, axis: Axis, quarter_turns: u32) -> Self {
+        let mut schematic = self.clone();
+
+        for _ in 0..quarter_turns % 4 {
+            schematic = schematic.rotate_once(axis);
+        }
+
+        schematic
+    }
+
+    // Quarter turn following the right hand rule around the given axis
+    fn rotate_once(&self, axis: Axis) -> Self {
+        let UVec3 {
+            x: sx,
+            y: sy,
+            z: sz,
+        } = self.size;
+
+        // Every block is overwritten, only the size changes
+        let mut schematic = Self {
+            size: match axis {
+                Axis::X => UVec3::new(sx, sz, sy),
+                Axis::Y => UVec3::new(sz, sy, sx),
+                Axis::Z => UVec3::new(sy, sx, sz),
+            },
+            blocks: self.blocks.clone(),
+        };
+
+        for z in 0..sz {
+            for y in 0..sy {
+                for x in 0..sx {
+                    let pos = match axis {
+                        Axis::X => UVec3::new(x, sz - 1 - z, y),
+                        Axis::Y => UVec3::new(z, y, sx - 1 - x),
+                        Axis::Z => UVec3::new(sy - 1 - y, x, z),
+                    };
+
+                    let index = schematic.index(pos);
+                    schematic.blocks[index] = self.blocks[self.index(UVec3::new(x, y, z))];
+                }
+            }
+        }
+
+        schematic
+    }
+
+    pub fn mirror(&self, axis: Axis) -> Self {
+        let mut schematic = self.clone();
+
+        for z in 0..self.size.z {
+            for y in 0..self.size.y {
+                for x in 0..self.size.x {
+                    let pos = match axis {
+                        Axis::X => UVec3::new(self.size.x - 1 - x, y, z),
+                        Axis::Y => UVec3::new(x, self.size.y - 1 - y, z),
+                        Axis::Z => UVec3::new(x, y, self.size.z - 1 - z),
+                    };
+
+                    let index = schematic.index(pos);
+                    schematic.blocks[index] = self.blocks[self.index(UVec3::new(x, y, z))];
+                }
+            }
+        }
+
+        schematic
+    }
+
+    // Iterates over the blocks in world coordinates once pasted at the given origin
+    pub fn blocks_at(
+        &self,
+        origin: BlockPos,
+        replace_air: bool,
+    ) -> impl Iterator<Item = (BlockPos, Block, u8)> + '_ {
+        (0..self.size.z)
+            .flat_map(move |z| {
+                (0..self.size.y)
+                    .flat_map(move |y| (0..self.size.x).map(move |x| UVec3::new(x, y, z)))
+            })
+            .filter_map(move |pos| {
+                let (block, health) = self.get(pos);
+
+                match block == Block::Air && !replace_air {
+                    true => None,
+                    false => Some((origin.offset(pos.as_ivec3()), block, health)),
+                }
+            })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut file = File::create(path)?;
+
+        file.write_all(SCHEMATIC_MAGIC)?;
+        file.write_all(&[SCHEMATIC_VERSION])?;
+        file.write_all(&self.size.x.to_le_bytes())?;
+        file.write_all(&self.size.y.to_le_bytes())?;
+        file.write_all(&self.size.z.to_le_bytes())?;
+        file.write_all(&self.blocks)?;
+
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let mut buffer = Vec::new();
+        File::open(path)?.read_to_end(&mut buffer)?;
+
+        if buffer.len() < 17 || &buffer[0..4] != SCHEMATIC_MAGIC {
+            return Err(eyre::eyre!("Not a schematic file"));
+        }
+
+        if buffer[4] != SCHEMATIC_VERSION {
+            return Err(eyre::eyre!(format!(
+                "Unsupported schematic version {}",
+                buffer[4]
+            )));
+        }
+
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([
+                buffer[offset],
+                buffer[offset + 1],
+                buffer[offset + 2],
+                buffer[offset + 3],
+            ])
+        };
+
+        let size = UVec3::new(read_u32(5), read_u32(9), read_u32(13));
+        let blocks = buffer[17..].to_vec();
+
+        let volume = Self::volume(size)?;
+
+        if blocks.len() != volume {
+            return Err(eyre::eyre!(format!(
+                "Schematic of size {:?} has {} blocks",
+                size,
+                blocks.len()
+            )));
+        }
+
+        Ok(Self { size, blocks })
+    }
+}
@@ -1,12 +1,12 @@
-use bevy::prelude::Commands;
+use bevy::prelude::{Commands, IVec3};
 
-use super::{blocks::Block, VoxelWorld};
+use super::{blocks::Block, coords::BlockPos, VoxelWorld};
 
-pub fn generate_tree(commands: &mut Commands, world: &VoxelWorld, x: i32, y: i32, z: i32) {
-    generate_oak(commands, world, x, y, z);
+pub fn generate_tree(commands: &mut Commands, world: &VoxelWorld, pos: BlockPos) {
+    generate_oak(commands, world, pos);
 }
 
-pub fn generate_oak(commands: &mut Commands, world: &VoxelWorld, x: i32, y: i32, z: i32) {
+pub fn generate_oak(commands: &mut Commands, world: &VoxelWorld, pos: BlockPos) {
     let radius = 4;
 
     let block = Block::Leaves;
@@ -17,18 +17,18 @@ pub fn generate_oak(commands: &mut Commands, world: &VoxelWorld, x: i32, y: i32,
                 if xx * xx + yy * yy + zz * zz <= radius * radius {
                     let yy = yy + 7;
 
-                    world.set_block(commands, x + xx, y + yy, z + zz, block, 15);
+                    world.set_block(commands, pos.offset(IVec3::new(xx, yy, zz)), block, 15);
                 }
             }
         }
     }
 
     for yy in 0..5 {
-        world.set_block(commands, x, y + yy, z, Block::Wood, 15);
+        world.set_block(commands, pos.offset(IVec3::new(0, yy, 0)), Block::Wood, 15);
     }
 }
 
-pub fn generate_fir(commands: &mut Commands, world: &VoxelWorld, x: i32, y: i32, z: i32) {
+pub fn generate_fir(commands: &mut Commands, world: &VoxelWorld, pos: BlockPos) {
     let radius = 4;
 
     let block = Block::Leaves;
@@ -39,13 +39,13 @@ pub fn generate_fir(commands: &mut Commands, world: &VoxelWorld, x: i32, y: i32,
                 if xx * xx + yy * yy + zz * zz <= radius * radius {
                     let yy = yy + 7;
 
-                    world.set_block(commands, x + xx, y + yy, z + zz, block, 15);
+                    world.set_block(commands, pos.offset(IVec3::new(xx, yy, zz)), block, 15);
                 }
             }
         }
     }
 
     for yy in 0..5 {
-        world.set_block(commands, x, y + yy, z, Block::Wood, 15);
+        world.set_block(commands, pos.offset(IVec3::new(0, yy, 0)), Block::Wood, 15);
     }
 }
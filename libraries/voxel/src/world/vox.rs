@@ -0,0 +1,361 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+};
+
+use bevy::{color::Srgba, prelude::*};
+
+use super::{blocks::Block, chunk::Chunk, coords::BlockPos, schematic::Schematic, VoxelWorld};
+
+// MagicaVoxel models can't be larger than 256 voxels on each axis
+pub const VOX_MODEL_SIZE: i32 = 256;
+
+#[derive(Debug, Event)]
+pub struct ExportVox {
+    pub min: BlockPos,
+    pub max: BlockPos,
+    pub path: String,
+}
+
+struct VoxModel {
+    offset: IVec3,
+    size: IVec3,
+    voxels: Vec<[u8; 4]>,
+}
+
+// Each (block, health) couple gets its own palette entry so the damages are kept in the export
+fn palette_index(block: Block, health: u8) -> u8 {
+    (block.as_u8() - 1) * 16 + health + 1
+}
+
+fn palette() -> Vec<u8> {
+    let mut palette = Vec::with_capacity(256 * 4);
+
+    for index in 1..=256u32 {
+        let block = Block::from(((index - 1) / 16 + 1) as u8);
+        let health = ((index - 1) % 16) as f32 / 15.0;
+
+        let color = block.color();
+        let color = LinearRgba::rgb(
+            color.red * health,
+            color.green * health,
+            color.blue * health,
+        );
+
+        match block {
+            Block::Air => palette.extend([0, 0, 0, 0]),
+            _ => palette.extend(Srgba::from(color).to_u8_array()),
+        }
+    }
+
+    palette
+}
+
+// Block and health of a palette entry. Palettes written by the export are mapped back exactly,
+// the colors of the other ones are matched with the closest block.
+fn palette_block(palette: &[u8], colors: &[u8], index: u8) -> (Block, u8) {
+    if index == 0 {
+        return (Block::Air, 15);
+    }
+
+    if colors == palette {
+        let index = index - 1;
+
+        return (Block::from(index / 16 + 1), index % 16);
+    }
+
+    let Some(rgba) = colors.chunks_exact(4).nth(index as usize - 1) else {
+        return (Block::Stone, 15);
+    };
+
+    let color = Vec3::from(
+        LinearRgba::from(Srgba::rgb_u8(rgba[0], rgba[1], rgba[2])).to_f32_array_no_alpha(),
+    );
+
+    let block = (1..16)
+        .map(Block::from)
+        .filter(|block| *block != Block::Air)
+        .min_by(|a, b| {
+            let distance = |block: &Block| {
+                Vec3::from(block.color().to_f32_array_no_alpha()).distance_squared(color)
+            };
+
+            distance(a).total_cmp(&distance(b))
+        })
+        .unwrap_or(Block::Stone);
+
+    (block, 15)
+}
+
+fn write_chunk(
+    buffer: &mut Vec<u8>,
+    id: &[u8; 4],
+    content: &[u8],
+    children: &[u8],
+) -> eyre::Result<()> {
+    buffer.write_all(id)?;
+    buffer.write_all(&(content.len() as u32).to_le_bytes())?;
+    buffer.write_all(&(children.len() as u32).to_le_bytes())?;
+    buffer.write_all(content)?;
+    buffer.write_all(children)?;
+
+    Ok(())
+}
+
+fn write_dict(buffer: &mut Vec<u8>, dict: &[(&str, String)]) -> eyre::Result<()> {
+    buffer.write_all(&(dict.len() as u32).to_le_bytes())?;
+
+    for (key, value) in dict {
+        buffer.write_all(&(key.len() as u32).to_le_bytes())?;
+        buffer.write_all(key.as_bytes())?;
+        buffer.write_all(&(value.len() as u32).to_le_bytes())?;
+        buffer.write_all(value.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn collect_models(
+    world: &VoxelWorld,
+    chunks: &Query<&Chunk>,
+    min: IVec3,
+    max: IVec3,
+) -> Vec<VoxModel> {
+    // MagicaVoxel is Z-up, so the world Y axis becomes the vox Z axis and the world Z axis is flipped
+    let extent = IVec3::new(max.x - min.x + 1, max.z - min.z + 1, max.y - min.y + 1);
+
+    let mut models = Vec::new();
+
+    for tile_z in (0..extent.z).step_by(VOX_MODEL_SIZE as usize) {
+        for tile_y in (0..extent.y).step_by(VOX_MODEL_SIZE as usize) {
+            for tile_x in (0..extent.x).step_by(VOX_MODEL_SIZE as usize) {
+                let offset = IVec3::new(tile_x, tile_y, tile_z);
+                let size = (extent - offset).min(IVec3::splat(VOX_MODEL_SIZE));
+
+                let mut voxels = Vec::new();
+
+                for z in 0..size.z {
+                    for y in 0..size.y {
+                        for x in 0..size.x {
+                            let vox = offset + IVec3::new(x, y, z);
+
+                            let pos = BlockPos::new(min.x + vox.x, min.y + vox.z, max.z - vox.y);
+
+                            let Some((block, health)) = world.get_block(chunks, pos) else {
+                                continue;
+                            };
+
+                            if block == Block::Air {
+                                continue;
+                            }
+
+                            voxels.push([x as u8, y as u8, z as u8, palette_index(block, health)]);
+                        }
+                    }
+                }
+
+                models.push(VoxModel {
+                    offset,
+                    size,
+                    voxels,
+                });
+            }
+        }
+    }
+
+    // Keep at least one (empty) model so the file stays valid
+    if models.iter().any(|model| !model.voxels.is_empty()) {
+        models.retain(|model| !model.voxels.is_empty());
+    } else {
+        models.truncate(1);
+    }
+
+    models
+}
+
+pub fn export_vox(
+    world: &VoxelWorld,
+    chunks: &Query<&Chunk>,
+    min: BlockPos,
+    max: BlockPos,
+    path: impl AsRef<Path>,
+) -> eyre::Result<()> {
+    let (min, max) = (min.min(*max), min.max(*max));
+
+    let models = collect_models(world, chunks, min, max);
+
+    let mut children = Vec::new();
+
+    for model in &models {
+        let mut size = Vec::new();
+        size.write_all(&model.size.x.to_le_bytes())?;
+        size.write_all(&model.size.y.to_le_bytes())?;
+        size.write_all(&model.size.z.to_le_bytes())?;
+
+        write_chunk(&mut children, b"SIZE", &size, &[])?;
+
+        let mut xyzi = Vec::new();
+        xyzi.write_all(&(model.voxels.len() as u32).to_le_bytes())?;
+        for voxel in &model.voxels {
+            xyzi.write_all(voxel)?;
+        }
+
+        write_chunk(&mut children, b"XYZI", &xyzi, &[])?;
+    }
+
+    // Scene graph : root transform -> group -> (transform -> shape) for each model
+    let mut root = Vec::new();
+    root.write_all(&0i32.to_le_bytes())?;
+    write_dict(&mut root, &[])?;
+    root.write_all(&1i32.to_le_bytes())?;
+    root.write_all(&(-1i32).to_le_bytes())?;
+    root.write_all(&(-1i32).to_le_bytes())?;
+    root.write_all(&1i32.to_le_bytes())?;
+    write_dict(&mut root, &[])?;
+
+    write_chunk(&mut children, b"nTRN", &root, &[])?;
+
+    let mut group = Vec::new();
+    group.write_all(&1i32.to_le_bytes())?;
+    write_dict(&mut group, &[])?;
+    group.write_all(&(models.len() as u32).to_le_bytes())?;
+    for index in 0..models.len() {
+        group.write_all(&(2 + 2 * index as i32).to_le_bytes())?;
+    }
+
+    write_chunk(&mut children, b"nGRP", &group, &[])?;
+
+    for (index, model) in models.iter().enumerate() {
+        let node = 2 + 2 * index as i32;
+
+        // MagicaVoxel places models by their center
+        let center = model.offset + model.size / 2;
+
+        let mut transform = Vec::new();
+        transform.write_all(&node.to_le_bytes())?;
+        write_dict(&mut transform, &[])?;
+        transform.write_all(&(node + 1).to_le_bytes())?;
+        transform.write_all(&(-1i32).to_le_bytes())?;
+        transform.write_all(&0i32.to_le_bytes())?;
+        transform.write_all(&1i32.to_le_bytes())?;
+        write_dict(
+            &mut transform,
+            &[("_t", format!("{} {} {}", center.x, center.y, center.z))],
+        )?;
+
+        write_chunk(&mut children, b"nTRN", &transform, &[])?;
+
+        let mut shape = Vec::new();
+        shape.write_all(&(node + 1).to_le_bytes())?;
+        write_dict(&mut shape, &[])?;
+        shape.write_all(&1i32.to_le_bytes())?;
+        shape.write_all(&(index as i32).to_le_bytes())?;
+        write_dict(&mut shape, &[])?;
+
+        write_chunk(&mut children, b"nSHP", &shape, &[])?;
+    }
+
+    write_chunk(&mut children, b"RGBA", &palette(), &[])?;
+
+    let mut buffer = Vec::new();
+    buffer.write_all(b"VOX ")?;
+    buffer.write_all(&150u32.to_le_bytes())?;
+    write_chunk(&mut buffer, b"MAIN", &[], &children)?;
+
+    if let Some(parent) = path.as_ref().parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    File::create(path)?.write_all(&buffer)?;
+
+    Ok(())
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> eyre::Result<u32> {
+    buffer
+        .get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| eyre::eyre!("Unexpected end of vox file"))
+}
+
+// Load the first model of a .vox file, the scene graph is ignored
+pub fn import_vox(path: impl AsRef<Path>) -> eyre::Result<Schematic> {
+    let mut buffer = Vec::new();
+    File::open(path)?.read_to_end(&mut buffer)?;
+
+    if buffer.len() < 20 || &buffer[0..4] != b"VOX " || &buffer[8..12] != b"MAIN" {
+        return Err(eyre::eyre!("Not a vox file"));
+    }
+
+    let mut size = None;
+    let mut voxels = None;
+    let mut colors = palette();
+
+    // The chunks of the model are the children of the main chunk
+    let mut offset = 20 + read_u32(&buffer, 12)? as usize;
+
+    while offset + 12 <= buffer.len() {
+        let id = &buffer[offset..offset + 4];
+        let length = read_u32(&buffer, offset + 4)? as usize;
+        let content = buffer
+            .get(offset + 12..offset + 12 + length)
+            .ok_or_else(|| eyre::eyre!("Unexpected end of vox file"))?;
+
+        match id {
+            b"SIZE" if size.is_none() => {
+                size = Some(UVec3::new(
+                    read_u32(content, 0)?,
+                    read_u32(content, 4)?,
+                    read_u32(content, 8)?,
+                ));
+            }
+            b"XYZI" if voxels.is_none() => {
+                let count = read_u32(content, 0)? as usize;
+
+                voxels = Some(
+                    content
+                        .get(4..4 + count * 4)
+                        .ok_or_else(|| eyre::eyre!("Unexpected end of vox file"))?
+                        .chunks_exact(4)
+                        .map(|voxel| [voxel[0], voxel[1], voxel[2], voxel[3]])
+                        .collect::<Vec<_>>(),
+                );
+            }
+            b"RGBA" => colors = content.to_vec(),
+            _ => {}
+        }
+
+        offset += 12 + length + read_u32(&buffer, offset + 8)? as usize;
+    }
+
+    let (Some(size), Some(voxels)) = (size, voxels) else {
+        return Err(eyre::eyre!("Vox file without model"));
+    };
+
+    if size.max_element() > VOX_MODEL_SIZE as u32 {
+        return Err(eyre::eyre!(format!(
+            "Vox model of size {:?} is too large",
+            size
+        )));
+    }
+
+    // MagicaVoxel is Z-up, the vox Y axis is flipped back into the Z axis as in the export
+    let mut schematic = Schematic::new(UVec3::new(size.x, size.z, size.y))?;
+
+    let palette = palette();
+
+    for [x, y, z, index] in voxels {
+        let (x, y, z) = (x as u32, y as u32, z as u32);
+
+        if x >= size.x || y >= size.y || z >= size.z {
+            continue;
+        }
+
+        let (block, health) = palette_block(&palette, &colors, index);
+
+        schematic.set(UVec3::new(x, z, size.y - 1 - y), block, health);
+    }
+
+    Ok(schematic)
+}